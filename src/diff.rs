@@ -0,0 +1,154 @@
+use crate::structs::*;
+
+/// Describes the differences between two songs, see `diff_songs`
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct SongDiff {
+    /// the header tags whose values differ, unknown tags are listed by their key
+    pub header: Vec<String>,
+    /// the lines that differ
+    pub lines: Vec<LineDiff>,
+}
+
+impl SongDiff {
+    /// returns whether the songs are the same
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty() && self.lines.is_empty()
+    }
+}
+
+/// Describes how a line differs between two songs
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum LineDiff {
+    /// the line only exists in the second song
+    Added(usize),
+    /// the line only exists in the first song
+    Removed(usize),
+    /// the line exists in both songs but differs
+    Modified {
+        /// the index of the line
+        index: usize,
+        /// whether the start or the relative value of the line break differs
+        start_changed: bool,
+        /// the notes that differ
+        notes: Vec<NoteDiff>,
+    },
+}
+
+/// Describes how a note differs between two versions of a line
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum NoteDiff {
+    /// the note only exists in the second line
+    Added(usize),
+    /// the note only exists in the first line
+    Removed(usize),
+    /// the note exists in both lines but differs
+    Modified(usize),
+}
+
+/// Compares two songs and returns their differences
+///
+/// The header is compared field by field. Lines and notes are compared by their index, so
+/// inserting a line in the middle marks all following lines as modified. This is not a minimal
+/// edit script, but good enough to show what was changed.
+///
+/// # Arguments
+/// * a - the original song
+/// * b - the changed song
+///
+pub fn diff_songs(a: &TXTSong, b: &TXTSong) -> SongDiff {
+    SongDiff {
+        header: diff_headers(&a.header, &b.header),
+        lines: diff_lines(&a.lines, &b.lines),
+    }
+}
+
+// returns the tags of all header fields that differ
+fn diff_headers(a: &Header, b: &Header) -> Vec<String> {
+    let mut changed = Vec::new();
+    let mut compare = |tag: &str, equal: bool| {
+        if !equal {
+            changed.push(String::from(tag));
+        }
+    };
+    compare("TITLE", a.title == b.title);
+    compare("ARTIST", a.artist == b.artist);
+    compare("MP3", a.audio_path == b.audio_path);
+    compare("BPM", a.bpm == b.bpm && a.bpm_changes == b.bpm_changes);
+    compare("GAP", a.gap == b.gap);
+    compare("COVER", a.cover_path == b.cover_path);
+    compare("BACKGROUND", a.background_path == b.background_path);
+    compare("VIDEO", a.video_path == b.video_path);
+    compare("VIDEOGAP", a.video_gap == b.video_gap);
+    compare("GENRE", a.genre == b.genre);
+    compare("EDITION", a.edition == b.edition);
+    compare("LANGUAGE", a.language == b.language);
+    compare("YEAR", a.year == b.year);
+    compare("RESOLUTION", a.resolution == b.resolution);
+    compare("NOTESGAP", a.notes_gap == b.notes_gap);
+    compare("ENCODING", a.encoding == b.encoding);
+    compare(
+        "MEDLEYSTARTBEAT",
+        a.medley_start_beat == b.medley_start_beat,
+    );
+    compare("MEDLEYENDBEAT", a.medley_end_beat == b.medley_end_beat);
+    compare("P1", a.p1 == b.p1);
+    compare("P2", a.p2 == b.p2);
+    compare("P3", a.p3 == b.p3);
+    compare("RELATIVE", a.relative == b.relative);
+
+    // unknown tags are compared by their key
+    let mut keys: Vec<&String> = a
+        .unknown
+        .iter()
+        .chain(b.unknown.iter())
+        .flat_map(|x| x.keys())
+        .collect();
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        let value_a = a.unknown.as_ref().and_then(|x| x.get(key));
+        let value_b = b.unknown.as_ref().and_then(|x| x.get(key));
+        compare(key, value_a == value_b);
+    }
+    changed
+}
+
+// compares the lines by their index
+fn diff_lines(a: &[Line], b: &[Line]) -> Vec<LineDiff> {
+    let mut diffs = Vec::new();
+    for index in 0..a.len().max(b.len()) {
+        match (a.get(index), b.get(index)) {
+            (Some(line_a), Some(line_b)) => {
+                if line_a != line_b {
+                    diffs.push(LineDiff::Modified {
+                        index,
+                        start_changed: line_a.start != line_b.start || line_a.rel != line_b.rel,
+                        notes: diff_notes(&line_a.notes, &line_b.notes),
+                    });
+                }
+            }
+            (Some(_), None) => diffs.push(LineDiff::Removed(index)),
+            (None, Some(_)) => diffs.push(LineDiff::Added(index)),
+            (None, None) => (),
+        }
+    }
+    diffs
+}
+
+// compares the notes by their index
+fn diff_notes(a: &[Note], b: &[Note]) -> Vec<NoteDiff> {
+    let mut diffs = Vec::new();
+    for index in 0..a.len().max(b.len()) {
+        match (a.get(index), b.get(index)) {
+            (Some(note_a), Some(note_b)) => {
+                if note_a != note_b {
+                    diffs.push(NoteDiff::Modified(index));
+                }
+            }
+            (Some(_), None) => diffs.push(NoteDiff::Removed(index)),
+            (None, Some(_)) => diffs.push(NoteDiff::Added(index)),
+            (None, None) => (),
+        }
+    }
+    diffs
+}
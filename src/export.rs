@@ -0,0 +1,121 @@
+use crate::structs::*;
+
+// the resolution of the exported MIDI files, a beat of a song is a sixteenth note
+const TICKS_PER_QUARTER: u16 = 480;
+const TICKS_PER_BEAT: f32 = TICKS_PER_QUARTER as f32 / 4.0;
+
+/// Converts a song to a karaoke MIDI file (`.kar`) and returns its bytes
+///
+/// The file has two tracks. The first one contains the tempo of the song and a MIDI note for
+/// every sung note, the pitch is moved by 36 so that pitch 0 (C2) becomes MIDI note 36, pitches
+/// outside of the MIDI range are clamped and notes without duration last one tick. The second
+/// one contains the lyrics as text events in the usual `.kar` layout: the `@K`/`@T` headers with
+/// title and artist, then the text of every note at its start, where a `/` marks the start of a
+/// new line. The texts are written as UTF-8.
+///
+/// The events are timed like in the game, i.e. with `Header::beat_to_ms`, so the gap of the song
+/// is kept. Player changes are ignored, the notes of all players end up in the same track. This
+/// assumes that the song uses absolute timing, see `to_absolute`.
+///
+/// # Arguments
+/// * song - the song to export
+///
+pub fn kar(song: &TXTSong) -> Vec<u8> {
+    let header = &song.header;
+    let ms_per_tick = header.ms_per_beat() / TICKS_PER_BEAT;
+    let tick =
+        |beat: Beat| -> u32 { (header.beat_to_ms(beat) / ms_per_tick).round().max(0.0) as u32 };
+
+    // the events are collected with their tick and sorted later, note offs come before note ons
+    // at the same tick, so a note can start where the previous one ends
+    let mut notes: Vec<(u32, u8, Vec<u8>)> = Vec::new();
+    let mut words: Vec<(u32, u8, Vec<u8>)> = Vec::new();
+    for line in song.lines.iter() {
+        let mut first_in_line = true;
+        for note in line.notes.iter() {
+            let (start, duration, pitch, text) =
+                match (note.start(), note.duration(), note.pitch(), note.text()) {
+                    (Some(start), Some(duration), Some(pitch), Some(text)) => {
+                        (start, duration, pitch, text)
+                    }
+                    _ => continue,
+                };
+            let key = pitch.saturating_add(36).clamp(0, 127) as u8;
+            // a note lasts at least one tick, so its note off never comes before its note on
+            let (on, off) = (tick(start), tick(start + duration));
+            notes.push((on, 1, vec![0x90, key, 0x64]));
+            notes.push((off.max(on + 1), 0, vec![0x80, key, 0x00]));
+
+            let text = if first_in_line {
+                format!("/{}", text)
+            } else {
+                String::from(text)
+            };
+            words.push((tick(start), 0, meta_event(0x01, text.as_bytes())));
+            first_in_line = false;
+        }
+    }
+
+    let tempo = (60_000_000.0 / header.bpm).round() as u32;
+    let mut notes_track = vec![(0, 0, meta_event(0x51, &tempo.to_be_bytes()[1..]))];
+    notes_track.extend(notes);
+    let title = format!("@T{}", header.title);
+    let artist = format!("@T{}", header.artist);
+    let mut words_track = vec![
+        (0, 0, meta_event(0x03, b"Words")),
+        (0, 0, meta_event(0x01, b"@KMIDI KARAOKE FILE")),
+        (0, 0, meta_event(0x01, title.as_bytes())),
+        (0, 0, meta_event(0x01, artist.as_bytes())),
+    ];
+    words_track.extend(words);
+
+    let mut file = Vec::new();
+    file.extend_from_slice(b"MThd");
+    file.extend_from_slice(&6u32.to_be_bytes());
+    // format 1 with two tracks
+    file.extend_from_slice(&1u16.to_be_bytes());
+    file.extend_from_slice(&2u16.to_be_bytes());
+    file.extend_from_slice(&TICKS_PER_QUARTER.to_be_bytes());
+    write_track(&mut file, notes_track);
+    write_track(&mut file, words_track);
+    file
+}
+
+// returns a meta event of the given type with the data
+fn meta_event(kind: u8, data: &[u8]) -> Vec<u8> {
+    let mut event = vec![0xff, kind];
+    write_variable_length(&mut event, data.len() as u32);
+    event.extend_from_slice(data);
+    event
+}
+
+// writes a track chunk with the events, they are sorted by their tick and order first
+fn write_track(file: &mut Vec<u8>, mut events: Vec<(u32, u8, Vec<u8>)>) {
+    // the sort is stable, so events with the same tick and order keep their sequence
+    events.sort_by_key(|&(tick, order, _)| (tick, order));
+    let mut track = Vec::new();
+    let mut last_tick = 0;
+    for (tick, _, event) in events {
+        write_variable_length(&mut track, tick - last_tick);
+        track.extend(event);
+        last_tick = tick;
+    }
+    write_variable_length(&mut track, 0);
+    track.extend_from_slice(&[0xff, 0x2f, 0x00]);
+
+    file.extend_from_slice(b"MTrk");
+    file.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    file.extend(track);
+}
+
+// writes a variable length quantity, seven bits per byte with the highest bit set on all but
+// the last byte
+fn write_variable_length(bytes: &mut Vec<u8>, value: u32) {
+    let mut groups = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        groups.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    bytes.extend(groups.iter().rev());
+}
@@ -1,4 +1,9 @@
 use crate::structs::*;
+#[cfg(feature = "file-support")]
+use encoding::{label::encoding_from_whatwg_label, EncoderTrap};
+use std::fmt::{self, Write};
+use std::io;
+use std::path::{Component, Path, PathBuf};
 
 error_chain! {
     errors {
@@ -7,104 +12,587 @@ error_chain! {
             description("invalid path encoding")
             display("invalid path encoding on tag: {}", tag)
         }
+        #[doc="the given encoding is unknown"]
+        UnknownEncoding(label: String) {
+            description("unknown encoding")
+            display("unknown encoding: {}", label)
+        }
+        #[doc="the text of a note contains a line break, line and note are indices into the lines"]
+        InvalidNoteText(line: usize, note: usize) {
+            description("line break in note text")
+            display("line break in the text of note {} of line {}", note, line)
+        }
+        #[doc="the value of a header tag contains a line break, unknown tags are named by their key"]
+        InvalidHeaderValue(tag: String) {
+            description("line break in header value")
+            display("line break in the value of tag: {}", tag)
+        }
+        #[doc="the text of a comment contains a line break, line is the line number of the comment"]
+        InvalidComment(line: u32) {
+            description("line break in comment")
+            display("line break in the comment at line {}", line)
+        }
+        #[doc="a line has a relative value, but the song does not use relative timing"]
+        RelativeLineBreak(line: usize) {
+            description("relative line break in absolute song")
+            display("line {} has a relative value, but the song is not relative", line)
+        }
     }
 }
 
+/// Options that change how songs are generated
+///
+/// The default options give the same result as `generate_song_txt`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GenerateOptions {
+    /// always write tag names in upper case, even if the header recorded another spelling
+    pub canonical_tag_names: bool,
+    /// leave out `#RELATIVE:NO`, absolute timing is the default anyway
+    pub omit_relative_no: bool,
+    /// the number of decimal places of the `#BPM`, `#GAP` and `#VIDEOGAP` values, they are
+    /// written as short as possible if this is `None`
+    pub float_decimals: Option<usize>,
+    /// write a comma instead of a dot as decimal separator, like exporters for some locales do
+    pub decimal_comma: bool,
+    /// the line ending written after every line of the header and the body
+    pub line_ending: LineEnding,
+    /// the WHATWG label of the encoding the song will be saved in, generating fails with
+    /// `InvalidPathEncoding` if a media path can not be represented in it
+    #[cfg(feature = "file-support")]
+    pub path_encoding: Option<String>,
+}
+
+/// Describes the line ending the generator writes
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    /// `\n`, like on Linux and macOS
+    #[default]
+    Lf,
+    /// `\r\n`, like on Windows
+    CrLf,
+}
+
 /// Converts a Song back to the Ultrastar Song format and returns it as a String
 ///
+/// The values of notes and line breaks are always separated by single spaces. Generating fails
+/// with `InvalidNoteText` if the text of a note contains a line break and with
+/// `InvalidHeaderValue` if a header value does, as the result could not be parsed again. It also
+/// fails with `RelativeLineBreak` if a line has a `rel` value but the
+/// header does not set `#RELATIVE:YES`, as the value would change the meaning of the song.
+///
 /// # Arguments
 /// * header - the Header struct of the song
 /// * lines - a vector of the songs lines
 ///
 pub fn generate_song_txt(header: &Header, lines: &[Line]) -> Result<String> {
-    // generate header
-    let mp3_str = header.audio_path.clone();
-    /*let mp3_str = match Some(header.audio_path) {
-        Some(x) => x,
-        None => bail!(ErrorKind::InvalidPathEncoding("MP3")),
-    }; */
-    let mut song_txt_str = format!(
-        "#TITLE:{}\n#ARTIST:{}\n#MP3:{}\n#BPM:{}\n",
-        header.title, header.artist, mp3_str, header.bpm
-    );
+    generate_song_txt_with_options(header, lines, &GenerateOptions::default())
+}
+
+/// Converts a Song back to the Ultrastar Song format with the given options and returns it as a
+/// String
+///
+/// If `options.path_encoding` is set, generating fails with `InvalidPathEncoding` for the first
+/// media path that can not be represented in that encoding. Like `generate_song_txt` it fails
+/// with `InvalidHeaderValue`, `InvalidNoteText` and `RelativeLineBreak` for values that can not
+/// be written.
+///
+/// # Arguments
+/// * header - the Header struct of the song
+/// * lines - a vector of the songs lines
+/// * options - the options to use while generating
+///
+pub fn generate_song_txt_with_options(
+    header: &Header,
+    lines: &[Line],
+    options: &GenerateOptions,
+) -> Result<String> {
+    #[cfg(feature = "file-support")]
+    {
+        if let Some(ref label) = options.path_encoding {
+            check_path_encoding(header, label)?;
+        }
+    }
+    check_lines(header, lines)?;
+
+    let mut song_txt_str = String::new();
+    let song_txt = SongTxt {
+        header,
+        lines,
+        comments: &[],
+        options,
+    };
+    write!(song_txt_str, "{}", song_txt).expect("writing to a String can not fail");
+    Ok(song_txt_str)
+}
+
+/// Converts a Song back to the Ultrastar Song format including comments and returns it as a
+/// String
+///
+/// Every comment is written at its line number if the body of the song reaches that line,
+/// comments after the last note are written before the end indicator. For an unchanged song
+/// this reproduces the positions from `parse_txt_lines_str_with_comments`. Besides the errors of
+/// `generate_song_txt` this fails with `InvalidComment` if the text of a comment contains a line
+/// break.
+///
+/// # Arguments
+/// * header - the Header struct of the song
+/// * lines - a vector of the songs lines
+/// * comments - the line numbers and texts of the comments, sorted by line number
+///
+pub fn generate_song_txt_with_comments(
+    header: &Header,
+    lines: &[Line],
+    comments: &[Comment],
+) -> Result<String> {
+    check_lines(header, lines)?;
+    if let Some((line, _)) = comments
+        .iter()
+        .find(|(_, text)| text.contains(['\n', '\r']))
+    {
+        bail!(ErrorKind::InvalidComment(*line));
+    }
+    let mut song_txt_str = String::new();
+    let options = GenerateOptions::default();
+    let song_txt = SongTxt {
+        header,
+        lines,
+        comments,
+        options: &options,
+    };
+    write!(song_txt_str, "{}", song_txt).expect("writing to a String can not fail");
+    Ok(song_txt_str)
+}
+
+/// Converts a Song back to the Ultrastar Song format with local media paths relative to a base
+/// directory and returns it as a String
+///
+/// This is the counterpart to the canonicalization of `parse_txt_song`, it keeps saved songs
+/// portable. Absolute local paths, with or without `file://`, are rewritten relative to `base`.
+/// Remote URLs and paths that are already relative are written unchanged, as are absolute paths
+/// that share no prefix with `base`. Only the file of the `#VIDEO` tag is rewritten, its
+/// parameters are kept. No file system access happens, so `base` should be canonicalized like
+/// the paths, e.g. the canonicalized directory the song is saved in.
+///
+/// # Arguments
+/// * header - the Header struct of the song
+/// * lines - a vector of the songs lines
+/// * base - the directory the paths are made relative to
+///
+pub fn generate_song_txt_relative<B: AsRef<Path>>(
+    header: &Header,
+    lines: &[Line],
+    base: B,
+) -> Result<String> {
+    let base = base.as_ref();
+    let mut header = header.clone();
+    header.audio_path = relative_path(&header.audio_path, base);
+    if let Some(ref cover_path) = header.cover_path {
+        header.cover_path = Some(relative_path(cover_path, base));
+    }
+    if let Some(ref background_path) = header.background_path {
+        header.background_path = Some(relative_path(background_path, base));
+    }
+    if let Some(ref video_path) = header.video_path {
+        // only the file is rewritten, the parameters are kept as they are
+        let spec = VideoSpec::from(video_path.as_ref());
+        header.video_path = Some(format!(
+            "{}{}",
+            relative_path(&spec.file, base),
+            spec.raw_params
+        ));
+    }
+    generate_song_txt(&header, lines)
+}
+
+// returns the path relative to the base if it is an absolute local path
+fn relative_path(path: &str, base: &Path) -> String {
+    if !path_is_local(path) {
+        return String::from(path);
+    }
+    let absolute = Path::new(path.strip_prefix("file://").unwrap_or(path));
+    if absolute.is_relative() {
+        return String::from(path);
+    }
+
+    let path_components: Vec<Component> = absolute.components().collect();
+    let base_components: Vec<Component> = base.components().collect();
+    let common = path_components
+        .iter()
+        .zip(base_components.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    // sharing only the root, e.g. `/` on Unix, is no shared prefix
+    let shared = path_components[..common]
+        .iter()
+        .filter(|x| !matches!(x, Component::RootDir | Component::Prefix(_)))
+        .count();
+    if shared == 0 {
+        return String::from(path);
+    }
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    for component in &path_components[common..] {
+        relative.push(component);
+    }
+    relative.display().to_string()
+}
+
+/// Converts a Header to an Ultrastar Song without any notes and returns it as a String
+///
+/// The result consists of the header tags and the end indicator, it can be used as template for
+/// a new song and parses to a single empty line. Like `generate_song_txt` it fails with
+/// `InvalidHeaderValue` if a header value contains a line break.
+///
+/// # Arguments
+/// * header - the Header struct of the song
+///
+pub fn generate_header_txt(header: &Header) -> Result<String> {
+    check_header(header)?;
+    let mut header_txt_str = String::new();
+    write_header(&mut header_txt_str, header, &GenerateOptions::default())
+        .expect("writing to a String can not fail");
+    header_txt_str.push('E');
+    Ok(header_txt_str)
+}
+
+/// Converts a Song back to the Ultrastar Song format and writes it into a `std::fmt::Write`
+///
+/// Nothing is written and `fmt::Error` is returned if the lines can not be written, see
+/// `generate_song_txt`.
+///
+/// # Arguments
+/// * w - the writer to write the song to
+/// * header - the Header struct of the song
+/// * lines - a vector of the songs lines
+///
+pub fn write_song_txt<W: fmt::Write>(w: &mut W, header: &Header, lines: &[Line]) -> fmt::Result {
+    check_lines(header, lines).map_err(|_| fmt::Error)?;
+    let options = GenerateOptions::default();
+    write!(
+        w,
+        "{}",
+        SongTxt {
+            header,
+            lines,
+            comments: &[],
+            options: &options,
+        }
+    )
+}
+
+/// Converts a Song back to the Ultrastar Song format and writes it into a `std::io::Write`
+///
+/// Nothing is written and an error of the kind `InvalidInput` is returned if the lines can not
+/// be written, see `generate_song_txt`.
+///
+/// # Arguments
+/// * w - the writer to write the song to, e.g. a file
+/// * header - the Header struct of the song
+/// * lines - a vector of the songs lines
+///
+pub fn write_song_txt_io<W: io::Write>(
+    w: &mut W,
+    header: &Header,
+    lines: &[Line],
+) -> io::Result<()> {
+    check_lines(header, lines)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let options = GenerateOptions::default();
+    write!(
+        w,
+        "{}",
+        SongTxt {
+            header,
+            lines,
+            comments: &[],
+            options: &options,
+        }
+    )
+}
+
+// checks that all media paths of the header can be encoded with the given encoding
+#[cfg(feature = "file-support")]
+fn check_path_encoding(header: &Header, label: &str) -> Result<()> {
+    let coder = match encoding_from_whatwg_label(label) {
+        Some(c) => c,
+        None => bail!(ErrorKind::UnknownEncoding(String::from(label))),
+    };
+    let paths = [
+        ("MP3", Some(&header.audio_path)),
+        ("COVER", header.cover_path.as_ref()),
+        ("BACKGROUND", header.background_path.as_ref()),
+        ("VIDEO", header.video_path.as_ref()),
+    ];
+    for (tag, path) in paths.iter() {
+        if let Some(path) = path {
+            if coder.encode(path, EncoderTrap::Strict).is_err() {
+                bail!(ErrorKind::InvalidPathEncoding(tag));
+            }
+        }
+    }
+    Ok(())
+}
+
+// checks that no header value contains a line break
+fn check_header(header: &Header) -> Result<()> {
+    let values = [
+        ("TITLE", Some(&header.title)),
+        ("ARTIST", Some(&header.artist)),
+        ("MP3", Some(&header.audio_path)),
+        ("COVER", header.cover_path.as_ref()),
+        ("BACKGROUND", header.background_path.as_ref()),
+        ("VIDEO", header.video_path.as_ref()),
+        ("GENRE", header.genre.as_ref()),
+        ("EDITION", header.edition.as_ref()),
+        ("LANGUAGE", header.language.as_ref()),
+        ("ENCODING", header.encoding.as_ref()),
+        ("P1", header.p1.as_ref()),
+        ("P2", header.p2.as_ref()),
+        ("P3", header.p3.as_ref()),
+    ];
+    for (tag, value) in values.iter() {
+        if value.is_some_and(|x| x.contains(['\n', '\r'])) {
+            bail!(ErrorKind::InvalidHeaderValue(String::from(*tag)));
+        }
+    }
+    for (key, value) in header.unknown.iter().flatten() {
+        if key.contains(['\n', '\r']) || value.contains(['\n', '\r']) {
+            bail!(ErrorKind::InvalidHeaderValue(key.clone()));
+        }
+    }
+    Ok(())
+}
+
+// checks the header like `check_header`, that no note text contains a line break and that only
+// relative songs have relative line breaks
+fn check_lines(header: &Header, lines: &[Line]) -> Result<()> {
+    check_header(header)?;
+    for (line_index, line) in lines.iter().enumerate() {
+        if line.rel.is_some() && header.relative != Some(true) {
+            bail!(ErrorKind::RelativeLineBreak(line_index));
+        }
+        for (note_index, note) in line.notes.iter().enumerate() {
+            if note.text().is_some_and(|x| x.contains(['\n', '\r'])) {
+                bail!(ErrorKind::InvalidNoteText(line_index, note_index));
+            }
+        }
+    }
+    Ok(())
+}
+
+// helper that formats a complete song, shared by all output functions
+struct SongTxt<'a> {
+    header: &'a Header,
+    lines: &'a [Line],
+    comments: &'a [Comment],
+    options: &'a GenerateOptions,
+}
+
+impl<'a> fmt::Display for SongTxt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut w = LineCounter {
+            inner: f,
+            lines: 0,
+            crlf: self.options.line_ending == LineEnding::CrLf,
+        };
+        let mut comments = self.comments.iter().peekable();
+        // writes the comments that belong before the next line
+        let mut write_comments = |w: &mut LineCounter<_>| -> fmt::Result {
+            while let Some((_, text)) = comments.next_if(|x| x.0 <= w.lines + 1) {
+                writeln!(w, "#{}", text)?;
+            }
+            Ok(())
+        };
+
+        // generate header
+        write_header(&mut w, self.header, self.options)?;
+
+        // generate lines
+        for line in self.lines.iter() {
+            if line.start != 0 as Beat {
+                write_comments(&mut w)?;
+                if let Some(rel) = line.rel {
+                    writeln!(w, "- {} {}", line.start, rel)?;
+                } else {
+                    writeln!(w, "- {}", line.start)?;
+                }
+            }
+            for note in line.notes.iter() {
+                write_comments(&mut w)?;
+                writeln!(w, "{}", note)?;
+            }
+        }
+        for (_, text) in comments {
+            writeln!(w, "#{}", text)?;
+        }
+        write!(w, "E")
+    }
+}
+
+// writer that counts the lines that were written, line endings are replaced by \r\n if crlf is
+// set
+struct LineCounter<W> {
+    inner: W,
+    lines: u32,
+    crlf: bool,
+}
+
+impl<W: Write> Write for LineCounter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.lines += s.matches('\n').count() as u32;
+        if !self.crlf {
+            return self.inner.write_str(s);
+        }
+        for (index, part) in s.split('\n').enumerate() {
+            if index != 0 {
+                self.inner.write_str("\r\n")?;
+            }
+            self.inner.write_str(part)?;
+        }
+        Ok(())
+    }
+}
+
+// writes the block of header tags
+pub(crate) fn write_header<W: Write>(
+    w: &mut W,
+    header: &Header,
+    options: &GenerateOptions,
+) -> fmt::Result {
+    // the recorded spelling of a tag or the tag itself
+    let tag = |tag: &'static str| -> &str {
+        if options.canonical_tag_names {
+            return tag;
+        }
+        match header.tag_spelling {
+            Some(ref spelling) => spelling.get(tag).map_or(tag, |x| x.as_ref()),
+            None => tag,
+        }
+    };
+
+    // formats a float value according to the options
+    let float = |x: f32| -> String {
+        let formatted = match options.float_decimals {
+            Some(decimals) => format!("{:.*}", decimals, x),
+            None => x.to_string(),
+        };
+        if options.decimal_comma {
+            formatted.replace('.', ",")
+        } else {
+            formatted
+        }
+    };
+
+    // the lines of the tags with their upper case name, in the fixed order
+    let mut tags: Vec<(&str, String)> = Vec::new();
+    tags.push(("TITLE", format!("#{}:{}", tag("TITLE"), header.title)));
+    tags.push(("ARTIST", format!("#{}:{}", tag("ARTIST"), header.artist)));
+    tags.push(("MP3", format!("#{}:{}", tag("MP3"), header.audio_path)));
+    match header.bpm_changes {
+        // the comma separates the entries, so it is never used as decimal separator here
+        Some(ref changes) => {
+            let changes: Vec<String> = changes
+                .iter()
+                .map(|&(beat, bpm)| format!("{} {}", beat, float(bpm).replace(',', ".")))
+                .collect();
+            tags.push(("BPM", format!("#{}:{}", tag("BPM"), changes.join(", "))));
+        }
+        None => tags.push(("BPM", format!("#{}:{}", tag("BPM"), float(header.bpm)))),
+    }
     if let Some(gap) = header.gap {
-        song_txt_str.push_str(&format!("#GAP:{}\n", gap));
+        tags.push(("GAP", format!("#{}:{}", tag("GAP"), float(gap))));
     }
-    if let Some(cover_path) = header.cover_path.clone() {
-        song_txt_str.push_str(&format!("#COVER:{}\n", cover_path));
+    if let Some(ref cover_path) = header.cover_path {
+        tags.push(("COVER", format!("#{}:{}", tag("COVER"), cover_path)));
     }
-    if let Some(background_path) = header.background_path.clone() {
-        song_txt_str.push_str(&format!("#BACKGROUND:{}\n", background_path));
+    if let Some(ref background_path) = header.background_path {
+        tags.push((
+            "BACKGROUND",
+            format!("#{}:{}", tag("BACKGROUND"), background_path),
+        ));
     }
-    if let Some(video_path) = header.video_path.clone() {
-        song_txt_str.push_str(&format!("#VIDEO:{}\n", video_path));
+    if let Some(ref video_path) = header.video_path {
+        tags.push(("VIDEO", format!("#{}:{}", tag("VIDEO"), video_path)));
     }
     if let Some(videogap) = header.video_gap {
-        song_txt_str.push_str(&format!("#VIDEOGAP:{}\n", videogap));
+        tags.push((
+            "VIDEOGAP",
+            format!("#{}:{}", tag("VIDEOGAP"), float(videogap)),
+        ));
     }
-    if let Some(genre) = header.genre.clone() {
-        song_txt_str.push_str(&format!("#GENRE:{}\n", genre));
+    if let Some(ref genre) = header.genre {
+        tags.push(("GENRE", format!("#{}:{}", tag("GENRE"), genre)));
     }
-    if let Some(edition) = header.edition.clone() {
-        song_txt_str.push_str(&format!("#EDITION:{}\n", edition));
+    if let Some(ref edition) = header.edition {
+        tags.push(("EDITION", format!("#{}:{}", tag("EDITION"), edition)));
     }
-    if let Some(language) = header.language.clone() {
-        song_txt_str.push_str(&format!("#LANGUAGE:{}\n", language));
+    if let Some(ref language) = header.language {
+        tags.push(("LANGUAGE", format!("#{}:{}", tag("LANGUAGE"), language)));
     }
     if let Some(year) = header.year {
-        song_txt_str.push_str(&format!("#YEAR:{}\n", year));
+        tags.push(("YEAR", format!("#{}:{}", tag("YEAR"), year)));
+    }
+    if let Some(resolution) = header.resolution {
+        tags.push((
+            "RESOLUTION",
+            format!("#{}:{}", tag("RESOLUTION"), resolution),
+        ));
+    }
+    if let Some(notes_gap) = header.notes_gap {
+        tags.push(("NOTESGAP", format!("#{}:{}", tag("NOTESGAP"), notes_gap)));
+    }
+    if let Some(ref encoding) = header.encoding {
+        tags.push(("ENCODING", format!("#{}:{}", tag("ENCODING"), encoding)));
+    }
+    // the combined `#MEDLEY` tag is always written as the two separate tags
+    if let Some(medley_start_beat) = header.medley_start_beat {
+        tags.push((
+            "MEDLEYSTARTBEAT",
+            format!("#{}:{}", tag("MEDLEYSTARTBEAT"), medley_start_beat),
+        ));
+    }
+    if let Some(medley_end_beat) = header.medley_end_beat {
+        tags.push((
+            "MEDLEYENDBEAT",
+            format!("#{}:{}", tag("MEDLEYENDBEAT"), medley_end_beat),
+        ));
+    }
+    if let Some(ref p1) = header.p1 {
+        tags.push(("P1", format!("#{}:{}", tag("P1"), p1)));
+    }
+    if let Some(ref p2) = header.p2 {
+        tags.push(("P2", format!("#{}:{}", tag("P2"), p2)));
+    }
+    if let Some(ref p3) = header.p3 {
+        tags.push(("P3", format!("#{}:{}", tag("P3"), p3)));
     }
     if let Some(relative) = header.relative {
         if relative {
-            song_txt_str.push_str("#RELATIVE:YES\n");
-        } else {
-            song_txt_str.push_str("#RELATIVE:NO\n");
+            tags.push(("RELATIVE", format!("#{}:YES", tag("RELATIVE"))));
+        } else if !options.omit_relative_no {
+            tags.push(("RELATIVE", format!("#{}:NO", tag("RELATIVE"))));
         }
     }
-    if let Some(unknown) = header.unknown.clone() {
-        for (key, value) in unknown.iter() {
-            song_txt_str.push_str(&format!("#{}:{}\n", key, value));
+    if let Some(ref unknown) = header.unknown {
+        let mut keys: Vec<&String> = unknown.keys().collect();
+        keys.sort();
+        for key in keys {
+            tags.push((key.as_str(), format!("#{}:{}", key, unknown[key])));
         }
     }
 
-    // generate lines
-    for line in lines.iter() {
-        if line.start != 0 {
-            if line.rel.is_some() {
-                song_txt_str.push_str(format!("- {} {}\n", line.start, line.rel.unwrap()).as_ref());
-            } else {
-                song_txt_str.push_str(format!("- {}\n", line.start).as_ref());
+    // tags from the recorded order come first, the others keep the fixed order
+    if let Some(ref order) = header.tag_order {
+        for key in order {
+            if let Some(index) = tags.iter().position(|&(x, _)| x == key) {
+                writeln!(w, "{}", tags.remove(index).1)?;
             }
         }
-        for note in line.notes.iter() {
-            match *note {
-                Note::Regular {
-                    start,
-                    duration,
-                    pitch,
-                    ref text,
-                } => song_txt_str
-                    .push_str(format!(": {} {} {} {}\n", start, duration, pitch, text).as_ref()),
-                Note::Golden {
-                    start,
-                    duration,
-                    pitch,
-                    ref text,
-                } => song_txt_str
-                    .push_str(format!("* {} {} {} {}\n", start, duration, pitch, text).as_ref()),
-                Note::Freestyle {
-                    start,
-                    duration,
-                    pitch,
-                    ref text,
-                } => song_txt_str
-                    .push_str(format!("F {} {} {} {}\n", start, duration, pitch, text).as_ref()),
-                Note::PlayerChange { player } => {
-                    song_txt_str.push_str(format!("P{}\n", player).as_ref())
-                }
-            };
-        }
     }
-    song_txt_str.push_str("E");
-    Ok(song_txt_str)
+    for (_, line) in tags {
+        writeln!(w, "{}", line)?;
+    }
+    Ok(())
 }
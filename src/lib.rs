@@ -1,5 +1,16 @@
 //! # Ultrastar TXT Library
 //! This is a small library that is able to parse and generate song files for the open source karaoke game Ultrastar.
+//!
+//! ## Features
+//! * `file-support` (default) - loading songs from files with encoding detection
+//! * `decimal-beats` - parse fractional beats, this changes `Beat` from `i32` to `f64`
+//! * `gzip` - loading gzip compressed song files, e.g. `song.txt.gz`
+//! * `zip` - loading songs from ZIP archives, e.g. downloaded song packs
+//!
+//! For targets that only parse and generate strings, e.g. WASM, the dependencies on `chardet`
+//! and `encoding` can be dropped with `default-features = false`. The `parse_*_str` and
+//! `generate_*` functions work the same without them, only the `loader` module and
+//! `GenerateOptions::path_encoding` are left out.
 #![deny(missing_docs)]
 
 #[macro_use]
@@ -8,20 +19,39 @@ extern crate error_chain;
 extern crate lazy_static;
 extern crate regex;
 
+/// this module contains functions to compare songs
+pub mod diff;
+/// this module contains functions to convert songs to other formats
+pub mod export;
 /// this module contains the generator
 pub mod generator;
 /// this module contains the parser
 pub mod parser;
 /// this module contains the structs that represent the parsed data
 pub mod structs;
+/// this module contains functions to classify the lines of a song
+pub mod syntax;
+/// this module contains functions that modify songs
+pub mod transform;
+/// this module contains functions to check songs for problems
+pub mod validate;
+/// this module contains the warnings about songs that could still be parsed
+pub mod warnings;
 
 #[cfg(feature = "file-support")]
 /// this module contains functions to parse songs from a path
 pub mod loader;
 
+pub use crate::diff::*;
+pub use crate::export::*;
+#[allow(ambiguous_glob_reexports)]
 pub use crate::generator::*;
 pub use crate::parser::*;
 pub use crate::structs::*;
+pub use crate::syntax::*;
+pub use crate::transform::*;
+pub use crate::validate::*;
+pub use crate::warnings::*;
 
 #[cfg(feature = "file-support")]
 pub use crate::loader::*;
@@ -1,9 +1,20 @@
 extern crate chardet;
 extern crate encoding;
+#[cfg(feature = "gzip")]
+extern crate flate2;
+#[cfg(feature = "zip")]
+extern crate zip;
 
-use crate::parser::{parse_txt_header_str, parse_txt_lines_str};
-use crate::structs::TXTSong;
-use std::fs::File;
+use crate::parser::{
+    self, parse_txt_header_str, parse_txt_header_str_with_warnings, parse_txt_lines_str,
+    parse_txt_lines_str_with_warnings,
+};
+pub use crate::structs::path_is_local;
+use crate::structs::{TXTSong, VideoSpec};
+use crate::validate::check_gap;
+use crate::warnings::Warning;
+use std::convert::TryFrom;
+use std::fs::{self, File};
 use std::io::Read;
 use std::path::{Path, PathBuf};
 
@@ -13,11 +24,16 @@ error_chain! {
         IOError {
             description("io error")
         }
-        #[doc="error in encoding detection"]
+        #[doc="error in encoding detection, no longer produced as unknown encodings fall back to UTF-8 or Windows-1252, kept for compatibility"]
         EncodingDetectionError {
             description("encoding detection error")
         }
-        #[doc="error while decoding"]
+        #[doc="the given encoding is unknown"]
+        UnknownEncoding(label: String) {
+            description("unknown encoding")
+            display("unknown encoding: {}", label)
+        }
+        #[doc="error while decoding, no longer produced as undecodable bytes are dropped, kept for compatibility"]
         DecodingError(msg: String) {
             description("decoding error")
             display("decoding error: {}", msg)
@@ -27,36 +43,130 @@ error_chain! {
             description("canonicalization error")
         }
         #[doc="error in parsing the song header"]
-        HeaderParsingError {
+        HeaderParsingError(msg: String) {
             description("header parsing error")
+            display("header parsing error: {}", msg)
         }
         #[doc="error in parsing the songs lines"]
-        LinesParsingError {
+        LinesParsingError(msg: String) {
             description("lines parsing error")
+            display("lines parsing error: {}", msg)
+        }
+        #[doc="error while reading an archive"]
+        ArchiveError {
+            description("archive error")
+        }
+        #[doc="the archive does not contain a song file"]
+        MissingSongFile {
+            description("no song file in archive")
         }
     }
 }
 
-fn read_file_to_string<P: AsRef<Path>>(p: P) -> Result<String> {
-    let p = p.as_ref();
+fn read_file_to_string<P: AsRef<Path>>(
+    p: P,
+    warnings: Option<&mut Vec<Warning>>,
+) -> Result<String> {
+    let (txt, _) = decode_bytes(&read_file_bytes(p.as_ref())?, None, warnings)?;
+    Ok(txt)
+}
+
+// reads the content of the file, compressed files are decompressed
+fn read_file_bytes(p: &Path) -> Result<Vec<u8>> {
     let mut f = File::open(p).chain_err(|| ErrorKind::IOError)?;
     let mut reader: Vec<u8> = Vec::new();
     f.read_to_end(&mut reader)
         .chain_err(|| ErrorKind::IOError)?;
 
-    // detect encoding and decode to String
-    let chardet_result = chardet::detect(&reader);
-    let whtwg_label = chardet::charset2encoding(&chardet_result.0);
-    let coder = encoding::label::encoding_from_whatwg_label(whtwg_label);
-    let file_content = match coder {
-        Some(c) => match c.decode(&reader, encoding::DecoderTrap::Ignore) {
-            Ok(x) => x,
-            Err(e) => bail!(ErrorKind::DecodingError(e.into_owned())),
+    // compressed files are detected by the magic bytes of gzip
+    #[cfg(feature = "gzip")]
+    let reader = if reader.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(&reader[..])
+            .read_to_end(&mut decompressed)
+            .chain_err(|| ErrorKind::IOError)?;
+        decompressed
+    } else {
+        reader
+    };
+
+    Ok(reader)
+}
+
+// decodes the bytes with the given encoding or the detected one if no encoding is given and
+// returns them with the WHATWG label of the encoding, bytes that can not be decoded are dropped
+// and reported if warnings are collected
+fn decode_bytes(
+    bytes: &[u8],
+    encoding_label: Option<&str>,
+    warnings: Option<&mut Vec<Warning>>,
+) -> Result<(String, &'static str)> {
+    let coder = match encoding_label {
+        Some(label) => match encoding::label::encoding_from_whatwg_label(label) {
+            Some(c) => c,
+            None => bail!(ErrorKind::UnknownEncoding(String::from(label))),
         },
-        None => bail!(ErrorKind::EncodingDetectionError),
+        None => {
+            // detect encoding
+            let chardet_result = chardet::detect(bytes);
+            let whtwg_label = chardet::charset2encoding(&chardet_result.0);
+            match encoding::label::encoding_from_whatwg_label(whtwg_label) {
+                Some(c) => c,
+                // fall back to the most common encodings of song files
+                None => {
+                    if std::str::from_utf8(bytes).is_ok() {
+                        encoding::all::UTF_8 as encoding::EncodingRef
+                    } else {
+                        encoding::all::WINDOWS_1252
+                    }
+                }
+            }
+        }
+    };
+
+    // decode to String, this is what `DecoderTrap::Ignore` does but counts the dropped bytes
+    let mut decoder = coder.raw_decoder();
+    let mut file_content = String::new();
+    let mut dropped: Option<(usize, usize)> = None;
+    let mut remaining = 0;
+    let mut drop_bytes = |from: usize, to: usize| {
+        let (count, _) = dropped.get_or_insert((0, from));
+        *count += to - from;
+    };
+    loop {
+        let (offset, err) = decoder.raw_feed(&bytes[remaining..], &mut file_content);
+        let unprocessed = remaining + offset;
+        match err {
+            Some(err) => {
+                remaining = (remaining as isize + err.upto) as usize;
+                drop_bytes(unprocessed, remaining);
+            }
+            None => {
+                remaining = bytes.len();
+                if let Some(err) = decoder.raw_finish(&mut file_content) {
+                    remaining = (remaining as isize + err.upto) as usize;
+                    drop_bytes(unprocessed, remaining);
+                }
+                if remaining >= bytes.len() {
+                    break;
+                }
+            }
+        }
+    }
+    if let (Some((count, offset)), Some(warnings)) = (dropped, warnings) {
+        warnings.push(Warning::UndecodableBytes { count, offset });
+    }
+
+    // strip byte order mark, after decoding it is the same char for every unicode encoding
+    let file_content = match file_content.strip_prefix('\u{feff}') {
+        Some(x) => String::from(x),
+        None => file_content,
     };
 
-    Ok(file_content)
+    Ok((
+        file_content,
+        coder.whatwg_name().unwrap_or_else(|| coder.name()),
+    ))
 }
 
 fn canonicalize_path(path: String, base_path: impl AsRef<Path>) -> Result<String> {
@@ -64,7 +174,6 @@ fn canonicalize_path(path: String, base_path: impl AsRef<Path>) -> Result<String
         path: &Option<P>,
         base_path: B,
     ) -> Result<Option<PathBuf>> {
-
         Ok(if let Some(ref path) = path {
             let mut tmp_path = PathBuf::from(base_path.as_ref());
             tmp_path.push(path);
@@ -84,7 +193,10 @@ fn canonicalize_path(path: String, base_path: impl AsRef<Path>) -> Result<String
             path.clone()
         };
         let path = PathBuf::from(path);
-        Ok(perform_canonicalization(&Some(path), base_path)?.unwrap().display().to_string())
+        Ok(perform_canonicalization(&Some(path), base_path)?
+            .unwrap()
+            .display()
+            .to_string())
     } else {
         Ok(path)
     }
@@ -97,37 +209,350 @@ fn canonicalize_path(path: String, base_path: impl AsRef<Path>) -> Result<String
 ///
 pub fn parse_txt_song<P: AsRef<Path>>(path: P) -> Result<TXTSong> {
     let path = path.as_ref();
-    let txt = read_file_to_string(path)?;
+    let mut txt_song = read_txt_song(path)?;
+
+    // canonicalize paths
+    if let Some(base_path) = path.parent() {
+        canonicalize_header_paths(&mut txt_song, base_path, None)?;
+    }
+
+    Ok(txt_song)
+}
+
+/// Takes path to a song file and returns TXTSong struct with canonicalized paths and the label of
+/// the detected encoding
+///
+/// The encoding is detected like in `parse_txt_song`, the label is the WHATWG name of the
+/// encoding that was used to decode the file, e.g. `windows-1252` or `utf-8`. It can be used as
+/// `GenerateOptions::path_encoding` to check the paths before the song is saved in the same
+/// encoding again.
+///
+/// # Arguments
+/// * path - the path to the song file to parse
+///
+pub fn parse_txt_song_detect_encoding<P: AsRef<Path>>(path: P) -> Result<(TXTSong, String)> {
+    let path = path.as_ref();
+    let (txt, label) = decode_bytes(&read_file_bytes(path)?, None, None)?;
+    let mut txt_song = parse_txt_str(&txt)?;
+
+    // canonicalize paths
+    if let Some(base_path) = path.parent() {
+        canonicalize_header_paths(&mut txt_song, base_path, None)?;
+    }
+
+    Ok((txt_song, String::from(label)))
+}
 
-    let mut txt_song = TXTSong {
-        header: parse_txt_header_str(txt.as_ref()).chain_err(|| ErrorKind::HeaderParsingError)?,
-        lines: parse_txt_lines_str(txt.as_ref()).chain_err(|| ErrorKind::LinesParsingError)?,
+/// Takes path to a song file that is most likely UTF-8 encoded and returns TXTSong struct with
+/// canonicalized paths
+///
+/// The encoding detection is skipped if the file is valid UTF-8, which is faster for large
+/// collections of modern song files. Otherwise the encoding is detected like in
+/// `parse_txt_song`. Unlike `parse_txt_song` a file that is valid UTF-8 is always decoded as
+/// UTF-8, even if the detection would have chosen another encoding.
+///
+/// # Arguments
+/// * path - the path to the song file to parse
+///
+pub fn parse_txt_song_utf8<P: AsRef<Path>>(path: P) -> Result<TXTSong> {
+    let path = path.as_ref();
+    let txt = match String::from_utf8(read_file_bytes(path)?) {
+        Ok(txt) => match txt.strip_prefix('\u{feff}') {
+            Some(x) => String::from(x),
+            None => txt,
+        },
+        Err(e) => decode_bytes(e.as_bytes(), None, None)?.0,
     };
+    let mut txt_song = parse_txt_str(&txt)?;
+
+    // canonicalize paths
+    if let Some(base_path) = path.parent() {
+        canonicalize_header_paths(&mut txt_song, base_path, None)?;
+    }
+
+    Ok(txt_song)
+}
+
+/// Takes path to a song file and returns TXTSong struct with canonicalized paths and the warnings
+/// that were found
+///
+/// The warnings of the decoding come first, then those of the header, the body, the gap (see
+/// `check_gap`) and the paths.
+/// Unlike `parse_txt_song` paths that can not be canonicalized are kept as they are and reported
+/// as warnings instead. Blank lines in the body are skipped and bytes that can not be decoded are
+/// dropped by both functions, but only reported here.
+///
+/// # Arguments
+/// * path - the path to the song file to parse
+///
+pub fn parse_txt_song_with_warnings<P: AsRef<Path>>(path: P) -> Result<(TXTSong, Vec<Warning>)> {
+    let path = path.as_ref();
+    let mut warnings = Vec::new();
+    let txt = read_file_to_string(path, Some(&mut warnings))?;
+    let (header, header_warnings) = parse_txt_header_str_with_warnings(&txt)
+        .map_err(|e| chain_parser_error(e, ErrorKind::HeaderParsingError))?;
+    warnings.extend(header_warnings);
+    let (lines, lines_warnings) = parse_txt_lines_str_with_warnings(&txt)
+        .map_err(|e| chain_parser_error(e, ErrorKind::LinesParsingError))?;
+    warnings.extend(lines_warnings);
+    let mut txt_song = TXTSong { header, lines };
+    warnings.extend(check_gap(&txt_song));
 
     // canonicalize paths
     if let Some(base_path) = path.parent() {
-        txt_song.header.audio_path = canonicalize_path(txt_song.header.audio_path, base_path)?;
+        canonicalize_header_paths(&mut txt_song, base_path, Some(&mut warnings))?;
+    }
+
+    Ok((txt_song, warnings))
+}
+
+/// Takes path to a song file and returns TXTSong struct with paths canonicalized against the
+/// given media directory instead of the directory of the song file
+///
+/// # Arguments
+/// * txt_path - the path to the song file to parse
+/// * media_base - the directory the media files of the song are located in
+///
+pub fn parse_txt_song_with_base<P: AsRef<Path>, B: AsRef<Path>>(
+    txt_path: P,
+    media_base: B,
+) -> Result<TXTSong> {
+    let mut txt_song = read_txt_song(txt_path.as_ref())?;
+    canonicalize_header_paths(&mut txt_song, media_base.as_ref(), None)?;
+    Ok(txt_song)
+}
+
+/// Takes the raw bytes of a song file and returns a TXTSong struct, the paths are not canonicalized
+///
+/// # Arguments
+/// * bytes - the content of the song file, e.g. an entry of an archive
+/// * encoding - the WHATWG label of the encoding of the bytes, it is detected if `None`
+///
+pub fn parse_txt_song_bytes(bytes: &[u8], encoding: Option<&str>) -> Result<TXTSong> {
+    let (txt, _) = decode_bytes(bytes, encoding, None)?;
+    parse_txt_str(&txt)
+}
 
-        if let Some(video_path) = txt_song.header.video_path {
-            txt_song.header.video_path = Some(canonicalize_path(video_path, base_path)?);
+/// Takes the path to a ZIP archive and returns the TXTSong struct of the first song file in it
+///
+/// The first entry with a `.txt` extension in the order of the archive is parsed, its encoding
+/// is detected like for other song files. The paths are not canonicalized, so they stay relative
+/// to the song file inside the archive.
+///
+/// # Arguments
+/// * zip_path - the path to the archive to parse
+///
+#[cfg(feature = "zip")]
+pub fn parse_txt_song_zip<P: AsRef<Path>>(zip_path: P) -> Result<TXTSong> {
+    let f = File::open(zip_path.as_ref()).chain_err(|| ErrorKind::IOError)?;
+    let mut archive = zip::ZipArchive::new(f).chain_err(|| ErrorKind::ArchiveError)?;
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .chain_err(|| ErrorKind::ArchiveError)?;
+        let is_song = entry.is_file()
+            && Path::new(entry.name())
+                .extension()
+                .is_some_and(|x| x.eq_ignore_ascii_case("txt"));
+        if is_song {
+            let mut bytes = Vec::new();
+            entry
+                .read_to_end(&mut bytes)
+                .chain_err(|| ErrorKind::ArchiveError)?;
+            return parse_txt_song_bytes(&bytes, None);
         }
-        if let Some(cover_path) = txt_song.header.cover_path {
-            txt_song.header.cover_path = Some(canonicalize_path(cover_path, base_path)?);
+    }
+    bail!(ErrorKind::MissingSongFile)
+}
+
+impl<'a> TryFrom<&'a [u8]> for TXTSong {
+    type Error = Error;
+
+    /// detects the encoding of the bytes and parses them like `parse_txt_song_bytes`, the paths
+    /// are not canonicalized
+    fn try_from(bytes: &'a [u8]) -> Result<TXTSong> {
+        parse_txt_song_bytes(bytes, None)
+    }
+}
+
+fn read_txt_song(path: &Path) -> Result<TXTSong> {
+    let txt = read_file_to_string(path, None)?;
+    parse_txt_str(&txt)
+}
+
+fn parse_txt_str(txt: &str) -> Result<TXTSong> {
+    Ok(TXTSong {
+        header: parse_txt_header_str(txt)
+            .map_err(|e| chain_parser_error(e, ErrorKind::HeaderParsingError))?,
+        lines: parse_txt_lines_str(txt)
+            .map_err(|e| chain_parser_error(e, ErrorKind::LinesParsingError))?,
+    })
+}
+
+// wraps a parser error, its message is kept in the new error and the error itself as its cause
+fn chain_parser_error<F>(e: parser::Error, kind: F) -> Error
+where
+    F: FnOnce(String) -> ErrorKind,
+{
+    let msg = e.to_string();
+    Error::with_chain(e, kind(msg))
+}
+
+/// Formats an error of the loader as `path:line: message` like compilers do, e.g. for command
+/// line tools
+///
+/// If the error was caused by the parser, its message is used and the line is taken from it.
+/// Errors without a line, like a missing essential header or an io error, are formatted as
+/// `path: message`.
+///
+/// # Arguments
+/// * path - the path to the song file the error occurred in
+/// * err - the error to format
+///
+pub fn format_error(path: &Path, err: &Error) -> String {
+    let parser_error =
+        std::error::Error::source(err).and_then(|e| e.downcast_ref::<parser::Error>());
+    let (line, msg) = match parser_error {
+        Some(e) => {
+            let line = match *e.kind() {
+                parser::ErrorKind::DuplicateHeader(line, ..)
+                | parser::ErrorKind::ValueError(line, ..)
+                | parser::ErrorKind::UnknownNoteType(line, ..)
+                | parser::ErrorKind::ParserFailure(line, ..)
+                | parser::ErrorKind::NotImplemented(line, ..) => Some(line),
+                _ => None,
+            };
+            (line, e.to_string())
         }
-        if let Some(background_path) = txt_song.header.background_path {
-            txt_song.header.background_path = Some(canonicalize_path(background_path, base_path)?);
+        None => (None, err.to_string()),
+    };
+    match line {
+        Some(line) => format!("{}:{}: {}", path.display(), line, msg),
+        None => format!("{}: {}", path.display(), msg),
+    }
+}
+
+// canonicalizes the media paths of the header, if warnings are collected paths that can not be
+// canonicalized are kept instead of failing
+fn canonicalize_header_paths(
+    txt_song: &mut TXTSong,
+    base_path: &Path,
+    mut warnings: Option<&mut Vec<Warning>>,
+) -> Result<()> {
+    let mut canonicalize = |tag: &'static str, path: String| -> Result<String> {
+        match canonicalize_path(path.clone(), base_path) {
+            Ok(x) => Ok(x),
+            Err(e) => match warnings {
+                Some(ref mut warnings) => {
+                    warnings.push(Warning::UncanonicalizedPath {
+                        tag,
+                        path: path.clone(),
+                    });
+                    Ok(path)
+                }
+                None => Err(e),
+            },
         }
+    };
+
+    let header = &mut txt_song.header;
+    header.audio_path = canonicalize("MP3", header.audio_path.clone())?;
+
+    if let Some(video_path) = header.video_path.take() {
+        // only the file is canonicalized, the parameters are kept as they are
+        let spec = VideoSpec::from(video_path.as_ref());
+        let file = canonicalize("VIDEO", spec.file)?;
+        header.video_path = Some(format!("{}{}", file, spec.raw_params));
+    }
+    if let Some(cover_path) = header.cover_path.take() {
+        header.cover_path = Some(canonicalize("COVER", cover_path)?);
+    }
+    if let Some(background_path) = header.background_path.take() {
+        header.background_path = Some(canonicalize("BACKGROUND", background_path)?);
     }
+    Ok(())
+}
 
-    Ok(txt_song)
+/// Parses all song files in a directory and returns the result for every file
+///
+/// All files with a `.txt` extension are parsed, subdirectories are ignored. An error in one
+/// file does not abort the other files. If the directory itself can not be read the result
+/// contains a single error for the directory.
+///
+/// # Arguments
+/// * dir - the directory to search for song files
+///
+pub fn load_songs_in_dir<P: AsRef<Path>>(dir: P) -> Vec<(PathBuf, Result<TXTSong>)> {
+    load_songs(dir.as_ref(), false, |_, _, _| ())
 }
 
-/// Returns whether the path references a local file.
-pub fn path_is_local(path: &str) -> bool {
-    // guess based on the occurence of a ://, but not a file://
-    if path.contains("://") && !path.starts_with("file://") {
-        false
-    } else {
-        true
+/// Parses all song files in a directory like `load_songs_in_dir` and reports the progress
+///
+/// The callback is called before each file is parsed with the index of the file, the total
+/// number of files and the path of the file. The index starts at 0, so it is also the number of
+/// files that are already parsed. The callback is not called if the directory can not be read.
+///
+/// # Arguments
+/// * dir - the directory to search for song files
+/// * progress - the callback that is called before each file is parsed
+///
+pub fn load_songs_in_dir_with_progress<P, F>(dir: P, progress: F) -> Vec<(PathBuf, Result<TXTSong>)>
+where
+    P: AsRef<Path>,
+    F: FnMut(usize, usize, &Path),
+{
+    load_songs(dir.as_ref(), false, progress)
+}
+
+/// Parses all song files in a directory and its subdirectories and returns the result for every
+/// file
+///
+/// See `load_songs_in_dir` for details.
+///
+/// # Arguments
+/// * dir - the directory to search for song files
+///
+pub fn load_songs_in_dir_recursive<P: AsRef<Path>>(dir: P) -> Vec<(PathBuf, Result<TXTSong>)> {
+    load_songs(dir.as_ref(), true, |_, _, _| ())
+}
+
+fn load_songs<F>(dir: &Path, recursive: bool, mut progress: F) -> Vec<(PathBuf, Result<TXTSong>)>
+where
+    F: FnMut(usize, usize, &Path),
+{
+    let mut paths = Vec::new();
+    if let Err(e) = find_txt_files(dir, recursive, &mut paths) {
+        return vec![(
+            dir.to_path_buf(),
+            Err(Error::with_chain(e, ErrorKind::IOError)),
+        )];
+    }
+    paths.sort();
+    let total = paths.len();
+    paths
+        .into_iter()
+        .enumerate()
+        .map(|(index, path)| {
+            progress(index, total, &path);
+            let result = parse_txt_song(&path);
+            (path, result)
+        })
+        .collect()
+}
+
+fn find_txt_files(dir: &Path, recursive: bool, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                find_txt_files(&path, recursive, paths)?;
+            }
+        } else if path
+            .extension()
+            .is_some_and(|x| x.eq_ignore_ascii_case("txt"))
+        {
+            paths.push(path);
+        }
     }
+    Ok(())
 }
@@ -1,34 +1,42 @@
-use crate::structs::{Header, Line, Note};
+use crate::structs::{Beat, Comment, Header, Line, Note, NoteKind, TXTSong};
+use crate::warnings::Warning;
 use regex::Regex;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::ops::RangeInclusive;
+
+// pattern for beat values in the lyric lines, might be fractional with decimal-beats enabled
+#[cfg(not(feature = "decimal-beats"))]
+const BEAT_PATTERN: &str = "-?[0-9]+";
+#[cfg(feature = "decimal-beats")]
+const BEAT_PATTERN: &str = "-?[0-9]+(?:\\.[0-9]+)?";
 
 error_chain! {
     errors {
         #[doc="duplicate header tag was found"]
         DuplicateHeader(line: u32, tag: &'static str) {
             description("duplicate header")
-            display("additional {} tag found in line: {}", line, tag)
+            display("additional {} tag found in line: {}", tag, line)
         }
         #[doc="an essential header is missing"]
         MissingEssential {
             description("essential header is missing")
         }
 
-        #[doc="value could not be parsed"]
-        ValueError(line: u32, field: &'static str) {
+        #[doc="value could not be parsed, the column is the byte offset of the value in the line"]
+        ValueError(line: u32, field: &'static str, content: String, column: Option<usize>) {
             description("could not parse value")
-            display("could not parse {} in line: {}", line, field)
+            display("could not parse {} in line {}: `{}`", field, line, content)
         }
         #[doc="an unknown note type was found"]
-        UnknownNoteType(line: u32) {
+        UnknownNoteType(line: u32, content: String) {
             description("unknown note type")
-            display("unknown note type in line: {}", line)
+            display("unknown note type in line {}: `{}`", line, content)
         }
         #[doc="could not parse the line at all"]
-        ParserFailure(line: u32) {
+        ParserFailure(line: u32, content: String) {
             description("could not parse line")
-            display("could not parse line: {}", line)
+            display("could not parse line {}: `{}`", line, content)
         }
         #[doc="song is missing the end terminator"]
         MissingEndIndicator {
@@ -37,20 +45,116 @@ error_chain! {
         #[doc="song file uses a feature that is not implemented"]
         NotImplemented(line: u32, feature: &'static str) {
             description("not implemented")
-            display("the feature {} in line {} is not implemented", line, feature)
+            display("the feature {} in line {} is not implemented", feature, line)
         }
     }
 }
 
+/// Options that change how songs are parsed
+///
+/// The default options give the same result as `parse_txt_header_str` and `parse_txt_lines_str`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// remove trailing whitespace from the text of every note
+    pub trim_note_text: bool,
+    /// what to do if a header tag is found more than once
+    pub on_duplicate: DuplicatePolicy,
+    /// reject notes with a pitch outside of this range with a `ValueError`, any pitch is accepted
+    /// if this is `None`
+    pub pitch_range: Option<RangeInclusive<i32>>,
+    /// accept a `-` without a start as line break at the end of the previous note instead of
+    /// failing with a `ValueError`
+    pub bare_line_breaks: bool,
+    /// the letters that start a note and the kind of note they stand for, replaces the mapping of
+    /// mainline UltraStar (`:` regular, `*` golden, `F` freestyle) if set
+    ///
+    /// `#`, `E` and `B` always keep their meaning, the generator always writes the mainline letters
+    pub note_types: Option<HashMap<char, NoteKind>>,
+    /// what to do if a note of unknown type is found
+    pub on_unknown_note: UnknownNotePolicy,
+    /// append a line in the header that does not start with `#` to the value of the previous tag
+    /// instead of ending the header, for charts that wrap long values
+    ///
+    /// the header still ends at the first blank line or line that looks like a part of the body
+    pub header_continuation: bool,
+    /// record the order of the header tags in `Header::tag_order`, so that the generator can
+    /// reproduce it
+    pub keep_tag_order: bool,
+}
+
+/// The pitches that can be represented as MIDI notes, pitch 0 (C2) is MIDI note 36
+///
+/// Useful as `ParseOptions::pitch_range` for songs that are converted to MIDI.
+pub const MIDI_PITCH_RANGE: RangeInclusive<i32> = -36..=91;
+
+/// Describes how the parser handles duplicate header tags
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// fail with a `DuplicateHeader` error
+    #[default]
+    Error,
+    /// keep the value of the first tag and ignore the others
+    KeepFirst,
+    /// keep the value of the last tag
+    KeepLast,
+}
+
+/// Describes how the parser handles notes of an unknown type
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum UnknownNotePolicy {
+    /// fail with an `UnknownNoteType` error
+    #[default]
+    Error,
+    /// skip the note, it is reported as `Warning::UnknownNoteType` if warnings are collected
+    Skip,
+}
+
 /// Parses the Header of a given Ultrastar Song and returns a Header struct
 ///
 /// # Arguments
 /// * txt_str  - a &str that contains the song to parse
 ///
 pub fn parse_txt_header_str(txt_str: &str) -> Result<Header> {
+    parse_txt_header_str_with_options(txt_str, &ParseOptions::default())
+}
+
+/// Parses the Header of a given Ultrastar Song with the given options and returns a Header struct
+///
+/// # Arguments
+/// * txt_str  - a &str that contains the song to parse
+/// * options - the options to use while parsing
+///
+pub fn parse_txt_header_str_with_options(txt_str: &str, options: &ParseOptions) -> Result<Header> {
+    parse_header(txt_str, options, &mut Vec::new())
+}
+
+/// Parses the Header of a given Ultrastar Song and returns a Header struct and the warnings that
+/// were found
+///
+/// Header tags without a value are reported as `Warning::EmptyTag`, lines starting with `#` that
+/// are not of the form `#TAG:value` are skipped and reported as `Warning::MalformedTag`.
+///
+/// # Arguments
+/// * txt_str  - a &str that contains the song to parse
+///
+pub fn parse_txt_header_str_with_warnings(txt_str: &str) -> Result<(Header, Vec<Warning>)> {
+    let mut warnings = Vec::new();
+    let header = parse_header(txt_str, &ParseOptions::default(), &mut warnings)?;
+    Ok((header, warnings))
+}
+
+// parses the header, problems that do not stop parsing are added to warnings
+fn parse_header(
+    txt_str: &str,
+    options: &ParseOptions,
+    warnings: &mut Vec<Warning>,
+) -> Result<Header> {
+    let policy = options.on_duplicate;
+
     let mut opt_title = None;
     let mut opt_artist = None;
     let mut opt_bpm = None;
+    let mut opt_bpm_changes = None;
     let mut opt_audio_path = None;
 
     let mut opt_gap = None;
@@ -62,174 +166,238 @@ pub fn parse_txt_header_str(txt_str: &str) -> Result<Header> {
     let mut opt_edition = None;
     let mut opt_language = None;
     let mut opt_year = None;
+    let mut opt_resolution = None;
+    let mut opt_notes_gap = None;
+    let mut opt_encoding = None;
+    let mut opt_medley_start_beat = None;
+    let mut opt_medley_end_beat = None;
+    let mut opt_p1 = None;
+    let mut opt_p2 = None;
+    let mut opt_p3 = None;
     let mut opt_relative = None;
     let mut opt_unknown: Option<HashMap<String, String>> = None;
+    let mut tag_spelling: HashMap<String, String> = HashMap::new();
+    let mut tag_order: Vec<String> = Vec::new();
 
     lazy_static! {
-        static ref RE: Regex = Regex::new(r"#([A-Z3a-z]*):(.*)").unwrap();
+        static ref RE: Regex = Regex::new(r"#([A-Z0-9a-z]+):(.*)").unwrap();
     }
 
-    for (line, line_count) in txt_str.lines().zip(1..) {
+    let txt = normalize_line_endings(txt_str);
+    for (line, line_count) in header_lines(&txt, options.header_continuation) {
+        let line = line.as_ref();
         let cap = match RE.captures(line) {
             Some(x) => x,
+            // a tag without name or colon does not end the header, it is skipped, this also
+            // skips banner comments of editors before the first tag
+            None if line.starts_with('#') => {
+                warnings.push(Warning::MalformedTag {
+                    line: line_count,
+                    text: String::from(&line[1..]),
+                });
+                continue;
+            }
             None => break,
         };
         let key = cap.get(1).unwrap().as_str();
         let value = cap.get(2).unwrap().as_str();
 
-        if value == "" {
-            //TODO: somehow warn about this
+        if value.is_empty() {
+            warnings.push(Warning::EmptyTag {
+                line: line_count,
+                tag: String::from(key),
+            });
             continue;
         }
 
-        match key {
-            "TITLE" => {
-                if opt_title.is_none() {
-                    opt_title = Some(String::from(value));
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "TITLE"));
-                }
-            }
-            "ARTIST" => {
-                if opt_artist.is_none() {
-                    opt_artist = Some(String::from(value));
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "ARTIST"));
-                }
-            }
-            "MP3" => {
-                if opt_audio_path.is_none() {
-                    opt_audio_path = Some(String::from(value));
-                        //Some(PathBuf::from(value));
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "MP3"));
-                }
-            }
+        // tags are recognized regardless of their case
+        let stored_tag = match key.to_ascii_uppercase().as_str() {
+            "TITLE" => set_header(&mut opt_title, line_count, "TITLE", policy, || {
+                Ok(String::from(value))
+            })?,
+            "ARTIST" => set_header(&mut opt_artist, line_count, "ARTIST", policy, || {
+                Ok(String::from(value))
+            })?,
+            "MP3" => set_header(&mut opt_audio_path, line_count, "MP3", policy, || {
+                Ok(String::from(value))
+            })?,
             "BPM" => {
-                if opt_bpm.is_none() {
-                    opt_bpm = match value.replace(",", ".").parse() {
-                        Ok(x) => Some(x),
-                        Err(_) => {
-                            bail!(ErrorKind::ValueError(line_count, "BPM"));
-                        }
-                    };
+                // a list of tempo changes separates the beat and the bpm of each entry by spaces
+                let changes = if value.trim().contains(char::is_whitespace) {
+                    Some(parse_bpm_changes(value, line, line_count)?)
                 } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "BPM"));
+                    None
+                };
+                let stored =
+                    set_header(&mut opt_bpm, line_count, "BPM", policy, || match changes {
+                        Some(ref changes) => Ok(changes[0].1),
+                        None => parse_bpm(value, line, line_count),
+                    })?;
+                if stored.is_some() {
+                    opt_bpm_changes = changes;
                 }
+                stored
             }
 
             // Optional Header fields
-            "GAP" => {
-                if opt_gap.is_none() {
-                    opt_gap = match value.replace(",", ".").parse() {
-                        Ok(x) => Some(x),
-                        Err(_) => {
-                            bail!(ErrorKind::ValueError(line_count, "GAP"));
-                        }
-                    };
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "GAP"));
-                }
-            }
-            "COVER" => {
-                if opt_cover_path.is_none() {
-                    opt_cover_path = Some(String::from(value));
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "COVER"));
-                }
-            }
-            "BACKGROUND" => {
-                if opt_background_path.is_none() {
-                    opt_background_path = Some(String::from(value));
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "BACKGROUND"));
-                }
-            }
-            "VIDEO" => {
-                if opt_video_path.is_none() {
-                    opt_video_path = Some(String::from(value));
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "VIDEO"));
-                }
-            }
-            "VIDEOGAP" => {
-                if opt_video_gap.is_none() {
-                    opt_video_gap = match value.replace(",", ".").parse() {
-                        Ok(x) => Some(x),
-                        Err(_) => {
-                            bail!(ErrorKind::ValueError(line_count, "VIDEOGAP"));
-                        }
-                    };
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "VIDEOGAP"));
-                }
-            }
-            "GENRE" => {
-                if opt_genre.is_none() {
-                    opt_genre = Some(String::from(value));
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "GENRE"));
-                }
-            }
-            "EDITION" => {
-                if opt_edition.is_none() {
-                    opt_edition = Some(String::from(value));
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "EDITION"));
-                }
-            }
-            "LANGUAGE" => {
-                if opt_language.is_none() {
-                    opt_language = Some(String::from(value));
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "LANGUAGE"));
-                }
-            }
-            "YEAR" => {
-                if opt_year.is_none() {
-                    opt_year = match value.parse() {
-                        Ok(x) => Some(x),
-                        Err(_) => {
-                            bail!(ErrorKind::ValueError(line_count, "YEAR"));
-                        }
-                    };
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "YEAR"));
+            "GAP" => set_header(&mut opt_gap, line_count, "GAP", policy, || {
+                parse_float(value, line, line_count, "GAP")
+            })?,
+            "COVER" => set_header(&mut opt_cover_path, line_count, "COVER", policy, || {
+                Ok(String::from(value))
+            })?,
+            "BACKGROUND" => set_header(
+                &mut opt_background_path,
+                line_count,
+                "BACKGROUND",
+                policy,
+                || Ok(String::from(value)),
+            )?,
+            "VIDEO" => set_header(&mut opt_video_path, line_count, "VIDEO", policy, || {
+                Ok(String::from(value))
+            })?,
+            "VIDEOGAP" => set_header(&mut opt_video_gap, line_count, "VIDEOGAP", policy, || {
+                parse_float(value, line, line_count, "VIDEOGAP")
+            })?,
+            "GENRE" => set_header(&mut opt_genre, line_count, "GENRE", policy, || {
+                Ok(String::from(value))
+            })?,
+            "EDITION" => set_header(&mut opt_edition, line_count, "EDITION", policy, || {
+                Ok(String::from(value))
+            })?,
+            "LANGUAGE" => set_header(&mut opt_language, line_count, "LANGUAGE", policy, || {
+                Ok(String::from(value))
+            })?,
+            "YEAR" => set_header(&mut opt_year, line_count, "YEAR", policy, || {
+                // only four digit years are plausible
+                match parse_value(value, line, line_count, "YEAR")? {
+                    x @ 1000..=9999 => Ok(x),
+                    _ => bail!(ErrorKind::ValueError(
+                        line_count,
+                        "YEAR",
+                        excerpt(line),
+                        Some(value_column(line, value))
+                    )),
                 }
+            })?,
+            "RESOLUTION" => set_header(
+                &mut opt_resolution,
+                line_count,
+                "RESOLUTION",
+                policy,
+                || parse_value(value, line, line_count, "RESOLUTION"),
+            )?,
+            "NOTESGAP" => set_header(&mut opt_notes_gap, line_count, "NOTESGAP", policy, || {
+                parse_value(value, line, line_count, "NOTESGAP")
+            })?,
+            "ENCODING" => set_header(&mut opt_encoding, line_count, "ENCODING", policy, || {
+                Ok(String::from(value))
+            })?,
+            "MEDLEYSTARTBEAT" => set_header(
+                &mut opt_medley_start_beat,
+                line_count,
+                "MEDLEYSTARTBEAT",
+                policy,
+                || parse_value(value, line, line_count, "MEDLEYSTARTBEAT"),
+            )?,
+            "MEDLEYENDBEAT" => set_header(
+                &mut opt_medley_end_beat,
+                line_count,
+                "MEDLEYENDBEAT",
+                policy,
+                || parse_value(value, line, line_count, "MEDLEYENDBEAT"),
+            )?,
+            "MEDLEY" => {
+                // the combined form is `start-end`, the start might be negative itself
+                let (start, end) = match value.get(1..).and_then(|x| x.find('-')) {
+                    Some(i) => (&value[..=i], &value[i + 2..]),
+                    None => bail!(ErrorKind::ValueError(
+                        line_count,
+                        "MEDLEY",
+                        excerpt(line),
+                        Some(value_column(line, value))
+                    )),
+                };
+                let start = parse_value(start.trim(), line, line_count, "MEDLEY")?;
+                let end = parse_value(end.trim(), line, line_count, "MEDLEY")?;
+                set_header(
+                    &mut opt_medley_start_beat,
+                    line_count,
+                    "MEDLEY",
+                    policy,
+                    || Ok(start),
+                )?;
+                set_header(
+                    &mut opt_medley_end_beat,
+                    line_count,
+                    "MEDLEY",
+                    policy,
+                    || Ok(end),
+                )?
             }
+            "P1" | "DUETSINGERP1" => set_header(&mut opt_p1, line_count, "P1", policy, || {
+                Ok(String::from(value))
+            })?,
+            "P2" | "DUETSINGERP2" => set_header(&mut opt_p2, line_count, "P2", policy, || {
+                Ok(String::from(value))
+            })?,
+            "P3" | "DUETSINGERP3" => set_header(&mut opt_p3, line_count, "P3", policy, || {
+                Ok(String::from(value))
+            })?,
             //TODO: check if relative changes line breaks
             "RELATIVE" => {
-                if opt_relative.is_none() {
-                    opt_relative = match value {
-                        "YES" | "yes" => Some(true),
-                        "NO" | "no" => Some(false),
-                        _ => {
-                            bail!(ErrorKind::ValueError(line_count, "RELATIVE"));
-                        }
-                    }
-                } else {
-                    bail!(ErrorKind::DuplicateHeader(line_count, "RELATIVE"));
-                }
+                set_header(
+                    &mut opt_relative,
+                    line_count,
+                    "RELATIVE",
+                    policy,
+                    || match value {
+                        "YES" | "yes" => Ok(true),
+                        "NO" | "no" => Ok(false),
+                        _ => bail!(ErrorKind::ValueError(
+                            line_count,
+                            "RELATIVE",
+                            excerpt(line),
+                            Some(value_column(line, value))
+                        )),
+                    },
+                )?
             }
             // use hashmap to store unknown tags
-            k => {
-                opt_unknown = match opt_unknown {
-                    Some(mut x) => {
-                        if !x.contains_key(k) {
-                            x.insert(String::from(k), String::from(value));
-                            Some(x)
-                        } else {
+            _ => {
+                let unknown = opt_unknown.get_or_insert_with(HashMap::new);
+                if unknown.contains_key(key) {
+                    match policy {
+                        DuplicatePolicy::Error => {
                             bail!(ErrorKind::DuplicateHeader(line_count, "UNKNOWN"));
                         }
+                        DuplicatePolicy::KeepFirst => continue,
+                        DuplicatePolicy::KeepLast => (),
                     }
-                    None => {
-                        let mut unknown = HashMap::new();
-                        unknown.insert(String::from(k), String::from(value));
-                        Some(unknown)
-                    }
-                };
+                }
+                unknown.insert(String::from(key), String::from(value));
+                if options.keep_tag_order {
+                    record_tag(&mut tag_order, key);
+                }
+                None
             }
         };
+
+        // remember how recognized tags were spelled
+        if let Some(tag) = stored_tag {
+            if key == tag {
+                tag_spelling.remove(tag);
+            } else {
+                tag_spelling.insert(String::from(tag), String::from(key));
+            }
+            // the combined medley tag is written as the two separate tags
+            if options.keep_tag_order && tag == "MEDLEY" {
+                record_tag(&mut tag_order, "MEDLEYSTARTBEAT");
+                record_tag(&mut tag_order, "MEDLEYENDBEAT");
+            } else if options.keep_tag_order {
+                record_tag(&mut tag_order, tag);
+            }
+        }
     }
 
     // build header from Options
@@ -240,6 +408,7 @@ pub fn parse_txt_header_str(txt_str: &str) -> Result<Header> {
             title,
             artist,
             bpm,
+            bpm_changes: opt_bpm_changes,
             audio_path,
 
             gap: opt_gap,
@@ -251,8 +420,26 @@ pub fn parse_txt_header_str(txt_str: &str) -> Result<Header> {
             edition: opt_edition,
             language: opt_language,
             year: opt_year,
+            resolution: opt_resolution,
+            notes_gap: opt_notes_gap,
+            encoding: opt_encoding,
+            medley_start_beat: opt_medley_start_beat,
+            medley_end_beat: opt_medley_end_beat,
+            p1: opt_p1,
+            p2: opt_p2,
+            p3: opt_p3,
             relative: opt_relative,
             unknown: opt_unknown,
+            tag_spelling: if tag_spelling.is_empty() {
+                None
+            } else {
+                Some(tag_spelling)
+            },
+            tag_order: if options.keep_tag_order {
+                Some(tag_order)
+            } else {
+                None
+            },
         };
         // header complete
         Ok(header)
@@ -262,175 +449,651 @@ pub fn parse_txt_header_str(txt_str: &str) -> Result<Header> {
     }
 }
 
+// appends a tag to the recorded order, tags that are found again keep their first position
+fn record_tag(tag_order: &mut Vec<String>, tag: &str) {
+    if !tag_order.iter().any(|x| x == tag) {
+        tag_order.push(String::from(tag));
+    }
+}
+
+// stores the value of a header tag in opt, duplicates are handled according to the policy
+// returns the tag if the value was stored
+fn set_header<T, F>(
+    opt: &mut Option<T>,
+    line_count: u32,
+    tag: &'static str,
+    policy: DuplicatePolicy,
+    parse: F,
+) -> Result<Option<&'static str>>
+where
+    F: FnOnce() -> Result<T>,
+{
+    if opt.is_some() {
+        match policy {
+            DuplicatePolicy::Error => bail!(ErrorKind::DuplicateHeader(line_count, tag)),
+            DuplicatePolicy::KeepFirst => return Ok(None),
+            DuplicatePolicy::KeepLast => (),
+        }
+    }
+    *opt = Some(parse()?);
+    Ok(Some(tag))
+}
+
+// parses a bpm, the timing of the notes is only defined for a positive bpm
+fn parse_bpm(value: &str, line: &str, line_count: u32) -> Result<f32> {
+    match parse_float(value, line, line_count, "BPM")? {
+        x if x > 0.0 => Ok(x),
+        _ => bail!(ErrorKind::ValueError(
+            line_count,
+            "BPM",
+            excerpt(line),
+            Some(value_column(line, value))
+        )),
+    }
+}
+
+// parses a list of tempo changes like `0 120, 64 140.5`, the beats have to be ascending
+fn parse_bpm_changes(value: &str, line: &str, line_count: u32) -> Result<Vec<(Beat, f32)>> {
+    let mut changes: Vec<(Beat, f32)> = Vec::new();
+    for entry in value.split(',') {
+        let entry = entry.trim();
+        let mut parts = entry.split_whitespace();
+        let change = match (parts.next(), parts.next(), parts.next()) {
+            (Some(beat), Some(bpm), None) => (
+                parse_value(beat, line, line_count, "BPM")?,
+                parse_bpm(bpm, line, line_count)?,
+            ),
+            _ => bail!(ErrorKind::ValueError(
+                line_count,
+                "BPM",
+                excerpt(line),
+                Some(value_column(line, entry))
+            )),
+        };
+        if changes.last().is_some_and(|&(beat, _)| beat >= change.0) {
+            bail!(ErrorKind::ValueError(
+                line_count,
+                "BPM",
+                excerpt(line),
+                Some(value_column(line, entry))
+            ));
+        }
+        changes.push(change);
+    }
+    Ok(changes)
+}
+
+// parses a header value, line is the complete line for error messages
+fn parse_value<T: std::str::FromStr>(
+    value: &str,
+    line: &str,
+    line_count: u32,
+    tag: &'static str,
+) -> Result<T> {
+    match value.parse() {
+        Ok(x) => Ok(x),
+        Err(_) => bail!(ErrorKind::ValueError(
+            line_count,
+            tag,
+            excerpt(line),
+            Some(value_column(line, value))
+        )),
+    }
+}
+
+// returns the column of a header value, the value has to be a part of the line
+fn value_column(line: &str, value: &str) -> usize {
+    value.as_ptr() as usize - line.as_ptr() as usize
+}
+
+// parses a float header value, a comma is accepted as decimal separator
+// if both a comma and a dot occur, the last one is the decimal separator and the other one
+// separates thousands, infinite values and NaN are rejected
+fn parse_float(value: &str, line: &str, line_count: u32, tag: &'static str) -> Result<f32> {
+    let normalized = match (value.rfind(','), value.rfind('.')) {
+        (Some(comma), Some(dot)) if comma > dot => value.replace('.', "").replace(',', "."),
+        (Some(_), Some(_)) => value.replace(',', ""),
+        _ => value.replace(',', "."),
+    };
+    match normalized.parse::<f32>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        _ => bail!(ErrorKind::ValueError(
+            line_count,
+            tag,
+            excerpt(line),
+            Some(value_column(line, value))
+        )),
+    }
+}
+
+// parses the value of a capture group of a body line, line is the complete line for error
+// messages
+fn parse_group<T: std::str::FromStr>(
+    cap: &regex::Captures,
+    group: usize,
+    line: &str,
+    line_count: u32,
+    field: &'static str,
+) -> Result<T> {
+    let value = cap.get(group).unwrap();
+    match value.as_str().parse() {
+        Ok(x) => Ok(x),
+        Err(_) => bail!(ErrorKind::ValueError(
+            line_count,
+            field,
+            excerpt(line),
+            Some(value.start())
+        )),
+    }
+}
+
+// shortens the content of a line for error messages
+fn excerpt(line: &str) -> String {
+    const MAX_CHARS: usize = 40;
+    if line.chars().count() > MAX_CHARS {
+        format!("{}...", line.chars().take(MAX_CHARS).collect::<String>())
+    } else {
+        String::from(line)
+    }
+}
+
 /// Parses the lyric lines of a given Ultarstar song and returns a vector of Line structs
 ///
+/// The values of notes and line breaks can be separated by any whitespace, e.g. tabs. The first
+/// line starts at beat 0 unless the body starts with a line break, then that line break starts
+/// the first line.
+///
 /// # Arguments
 /// * txt_str  - a &str that contains the song to parse
 ///
 pub fn parse_txt_lines_str(txt_str: &str) -> Result<Vec<Line>> {
-    lazy_static! {
-        static ref LINE_RE: Regex = Regex::new("^-\\s?(-?[0-9]+)\\s*$").unwrap();
-        static ref LREL_RE: Regex = Regex::new("^-\\s?(-?[0-9]+)\\s+(-?[0-9]+)").unwrap();
-        static ref NOTE_RE: Regex =
-            Regex::new("^(.)\\s*(-?[0-9]+)\\s+(-?[0-9]+)\\s+(-?[0-9]+)\\s?(.*)").unwrap();
-        static ref DUET_RE: Regex = Regex::new("^P\\s?(-?[0-9]+)").unwrap();
-    }
+    Ok(parse_lines(txt_str, &ParseOptions::default(), Err, None)?.0)
+}
 
-    let mut lines_vec = Vec::new();
-    let mut current_line = Line {
-        start: 0,
-        rel: None,
-        notes: Vec::new(),
-    };
+/// Parses the lyric lines of a given Ultarstar song with the given options and returns a vector
+/// of Line structs
+///
+/// # Arguments
+/// * txt_str  - a &str that contains the song to parse
+/// * options - the options to use while parsing
+///
+pub fn parse_txt_lines_str_with_options(
+    txt_str: &str,
+    options: &ParseOptions,
+) -> Result<Vec<Line>> {
+    Ok(parse_lines(txt_str, options, Err, None)?.0)
+}
 
-    let mut found_end_indicator = false;
-    for (line, line_count) in txt_str.lines().zip(1..) {
-        let first_char = match line.chars().nth(0) {
-            Some(x) => x,
-            None => bail!(ErrorKind::ParserFailure(line_count)),
-        };
+/// Parses the lyric lines of a given Ultarstar song and returns a vector of Line structs and the
+/// comments found in the body of the song
+///
+/// Comments are lines starting with `#` that follow the first line that is not a header tag.
+/// Each comment is returned with its line number and its text without the leading `#`.
+///
+/// # Arguments
+/// * txt_str  - a &str that contains the song to parse
+///
+pub fn parse_txt_lines_str_with_comments(txt_str: &str) -> Result<(Vec<Line>, Vec<Comment>)> {
+    parse_lines(txt_str, &ParseOptions::default(), Err, None)
+}
 
-        // ignore header
-        if first_char == '#' {
-            continue;
+/// Parses the lyric lines of a given Ultarstar song and returns a vector of Line structs and the
+/// warnings that were found
+///
+/// Blank lines in the body are skipped like in `parse_txt_lines_str` and reported as
+/// `Warning::BlankLine`. Comments in the body are reported as `Warning::CommentLine`.
+///
+/// # Arguments
+/// * txt_str  - a &str that contains the song to parse
+///
+pub fn parse_txt_lines_str_with_warnings(txt_str: &str) -> Result<(Vec<Line>, Vec<Warning>)> {
+    let mut warnings = Vec::new();
+    let (lines, _) = parse_lines(txt_str, &ParseOptions::default(), Err, Some(&mut warnings))?;
+    Ok((lines, warnings))
+}
+
+/// Parses the lyric lines of a given Ultarstar song with the given options and returns a vector
+/// of Line structs and the warnings that were found
+///
+/// See `parse_txt_lines_str_with_warnings` for the warnings, notes that are skipped because of
+/// `options.on_unknown_note` are reported as `Warning::UnknownNoteType`.
+///
+/// # Arguments
+/// * txt_str  - a &str that contains the song to parse
+/// * options - the options to use while parsing
+///
+pub fn parse_txt_lines_str_with_options_and_warnings(
+    txt_str: &str,
+    options: &ParseOptions,
+) -> Result<(Vec<Line>, Vec<Warning>)> {
+    let mut warnings = Vec::new();
+    let (lines, _) = parse_lines(txt_str, options, Err, Some(&mut warnings))?;
+    Ok((lines, warnings))
+}
+
+/// Parses the lyric lines of a given Ultarstar song and returns a vector of Line structs or
+/// all errors that were found
+///
+/// Unlike `parse_txt_lines_str` this does not stop at the first malformed line. Lines that
+/// fail to parse are skipped and their errors are collected, so that a file can be fixed in one
+/// go.
+///
+/// # Arguments
+/// * txt_str  - a &str that contains the song to parse
+///
+pub fn parse_txt_lines_str_collect(txt_str: &str) -> std::result::Result<Vec<Line>, Vec<Error>> {
+    let mut errors = Vec::new();
+    let result = parse_lines(
+        txt_str,
+        &ParseOptions::default(),
+        |e| {
+            errors.push(e);
+            Ok(())
+        },
+        None,
+    );
+    match result {
+        Ok((lines, _)) => {
+            if errors.is_empty() {
+                Ok(lines)
+            } else {
+                Err(errors)
+            }
         }
+        Err(e) => {
+            errors.push(e);
+            Err(errors)
+        }
+    }
+}
 
-        // not implemented
-        if first_char == 'B' {
-            bail!(ErrorKind::NotImplemented(line_count, "variable bpm"));
+/// Parses a string that contains several songs one after the other and returns a vector of
+/// TXTSong structs
+///
+/// Every song ends with its end indicator and the next song starts with its header, blank lines
+/// between the songs are ignored. The line numbers in errors refer to the whole string.
+///
+/// # Arguments
+/// * txt_str  - a &str that contains the songs to parse
+///
+pub fn parse_txt_songs_str(txt_str: &str) -> Result<Vec<TXTSong>> {
+    let txt_str = &*normalize_line_endings(txt_str);
+    let mut songs = Vec::new();
+    // the byte offset and the number of lines before the current song, if it has started
+    let mut song_start: Option<(usize, u32)> = None;
+    let mut offset = 0;
+
+    for (line, line_count) in txt_str.split_inclusive('\n').zip(0..) {
+        let line_start = offset;
+        offset += line.len();
+        if song_start.is_none() && !line.trim().is_empty() {
+            song_start = Some((line_start, line_count));
+        }
+        if line.starts_with('E') {
+            if let Some((start, skipped_lines)) = song_start.take() {
+                songs.push(parse_song_segment(&txt_str[start..offset], skipped_lines)?);
+            }
         }
+    }
+    // a song without end indicator is left
+    if let Some((start, skipped_lines)) = song_start {
+        songs.push(parse_song_segment(&txt_str[start..], skipped_lines)?);
+    }
+    Ok(songs)
+}
 
-        // stop parsing after end symbol
-        if first_char == 'E' {
-            lines_vec.push(current_line);
-            found_end_indicator = true;
-            break;
+// converts classic Mac line endings, i.e. a single \r, to \n, the line numbers are kept
+// only files without any \n are converted, so a stray \r in other files does not add lines
+fn normalize_line_endings(txt_str: &str) -> Cow<'_, str> {
+    if txt_str.contains('\r') && !txt_str.contains('\n') {
+        Cow::Owned(txt_str.replace('\r', "\n"))
+    } else {
+        Cow::Borrowed(txt_str)
+    }
+}
+
+// parses a single song of a string with several songs, skipped_lines is the number of lines
+// before the song
+fn parse_song_segment(txt_str: &str, skipped_lines: u32) -> Result<TXTSong> {
+    Ok(TXTSong {
+        header: parse_txt_header_str(txt_str).map_err(|e| offset_error(e, skipped_lines))?,
+        lines: parse_txt_lines_str(txt_str).map_err(|e| offset_error(e, skipped_lines))?,
+    })
+}
+
+// moves the line number of an error by the given number of lines
+fn offset_error(e: Error, lines: u32) -> Error {
+    let kind = match *e.kind() {
+        ErrorKind::DuplicateHeader(line, tag) => ErrorKind::DuplicateHeader(line + lines, tag),
+        ErrorKind::ValueError(line, field, ref content, column) => {
+            ErrorKind::ValueError(line + lines, field, content.clone(), column)
+        }
+        ErrorKind::UnknownNoteType(line, ref content) => {
+            ErrorKind::UnknownNoteType(line + lines, content.clone())
+        }
+        ErrorKind::ParserFailure(line, ref content) => {
+            ErrorKind::ParserFailure(line + lines, content.clone())
         }
+        ErrorKind::NotImplemented(line, feature) => {
+            ErrorKind::NotImplemented(line + lines, feature)
+        }
+        _ => return e,
+    };
+    Error::from(kind)
+}
 
-        // current line is a note
-        if NOTE_RE.is_match(line) {
-            let cap = NOTE_RE.captures(line).unwrap();
+lazy_static! {
+    pub(crate) static ref LINE_RE: Regex =
+        Regex::new(&format!("^-\\s?({})\\s*$", BEAT_PATTERN)).unwrap();
+    pub(crate) static ref LREL_RE: Regex =
+        Regex::new(&format!("^-\\s?({0})\\s+({0})", BEAT_PATTERN)).unwrap();
+    pub(crate) static ref NOTE_RE: Regex = Regex::new(&format!(
+        "^(.)\\s*({0})\\s+({0})\\s+(-?[0-9]+)\\s?(.*)",
+        BEAT_PATTERN
+    ))
+    .unwrap();
+    pub(crate) static ref DUET_RE: Regex = Regex::new("^P\\s?(-?[0-9]+)").unwrap();
+}
 
-            let note_start = match cap.get(2).unwrap().as_str().parse() {
-                Ok(x) => x,
-                Err(_) => {
-                    bail!(ErrorKind::ValueError(line_count, "note start"));
-                }
-            };
-            let note_duration = match cap.get(3).unwrap().as_str().parse() {
-                Ok(x) => {
-                    if x >= 0 {
-                        x
-                    } else {
-                        bail!(ErrorKind::ValueError(line_count, "note duration"));
+// returns the lines of the header with their line number and the first line after it, wrapped
+// values are joined with the line of their tag if continuation is enabled
+fn header_lines(txt_str: &str, continuation: bool) -> Vec<(Cow<'_, str>, u32)> {
+    let mut lines: Vec<(Cow<'_, str>, u32)> = Vec::new();
+    for (line, line_count) in txt_str.lines().zip(1..) {
+        // a stray carriage return might survive if lines end with \r\r\n
+        let line = line.trim_end_matches('\r');
+        if !line.starts_with('#') {
+            match lines.last_mut() {
+                Some((previous, _)) if continuation && !is_body_start(line) => {
+                    let value = previous.to_mut();
+                    if !value.ends_with(':') {
+                        value.push(' ');
                     }
+                    value.push_str(line.trim());
+                    continue;
                 }
-                Err(_) => {
-                    bail!(ErrorKind::ValueError(line_count, "note duration"));
-                }
-            };
-            let note_pitch = match cap.get(4).unwrap().as_str().parse() {
-                Ok(x) => x,
-                Err(_) => {
-                    bail!(ErrorKind::ValueError(line_count, "note pitch"));
+                _ => {
+                    lines.push((Cow::Borrowed(line), line_count));
+                    break;
                 }
-            };
-            let note_text = cap.get(5).unwrap().as_str();
-
-            let note = match cap.get(1).unwrap().as_str() {
-                ":" => Note::Regular {
-                    start: note_start,
-                    duration: note_duration,
-                    pitch: note_pitch,
-                    text: String::from(note_text),
-                },
-                "*" => Note::Golden {
-                    start: note_start,
-                    duration: note_duration,
-                    pitch: note_pitch,
-                    text: String::from(note_text),
-                },
-                "F" => Note::Freestyle {
-                    start: note_start,
-                    duration: note_duration,
-                    pitch: note_pitch,
-                    text: String::from(note_text),
-                },
-                _ => bail!(ErrorKind::UnknownNoteType(line_count)),
-            };
-
-            current_line.notes.push(note);
-            continue;
+            }
         }
+        lines.push((Cow::Borrowed(line), line_count));
+    }
+    lines
+}
 
-        // current line is a line break
-        if LINE_RE.is_match(line) {
-            // push old line to the Line vector and prepare new line
-            lines_vec.push(current_line);
-            let cap = LINE_RE.captures(line).unwrap();
-            let line_start = match cap.get(1).unwrap().as_str().parse() {
-                Ok(x) => x,
-                Err(_) => {
-                    bail!(ErrorKind::ValueError(line_count, "line start"));
-                }
-            };
-            current_line = Line {
-                start: line_start,
-                rel: None,
-                notes: Vec::new(),
-            };
+// returns whether a line that does not start with # ends the header, the other ones are
+// continued header values
+fn is_body_start(line: &str) -> bool {
+    let line = line.trim_end();
+    line.is_empty()
+        || line == "-"
+        || line == "E"
+        || line.starts_with("B ")
+        || NOTE_RE.is_match(line)
+        || LINE_RE.is_match(line)
+        || LREL_RE.is_match(line)
+        || DUET_RE.is_match(line)
+}
+
+// the different kinds of lines that can be found in the body of a song
+enum BodyLine<'a> {
+    Hash(&'a str),
+    End,
+    Note(Note),
+    LineBreak(Line),
+    BareLineBreak,
+}
+
+// parses the lyric lines and comments, on_error decides whether an error in a single line aborts
+// parsing, blank lines are skipped and reported if warnings are collected
+fn parse_lines<F>(
+    txt_str: &str,
+    options: &ParseOptions,
+    mut on_error: F,
+    mut warnings: Option<&mut Vec<Warning>>,
+) -> Result<(Vec<Line>, Vec<Comment>)>
+where
+    F: FnMut(Error) -> Result<()>,
+{
+    let mut lines_vec = Vec::new();
+    let mut comments = Vec::new();
+    let mut in_header = true;
+    let mut current_line = Line {
+        start: 0 as Beat,
+        rel: None,
+        notes: Vec::new(),
+    };
+
+    for (line, line_count) in normalize_line_endings(txt_str).lines().zip(1..) {
+        if line.trim_end_matches('\r').is_empty() {
+            if let Some(ref mut warnings) = warnings {
+                warnings.push(Warning::BlankLine { line: line_count });
+            }
             continue;
         }
-
-        // current line is a relative line break
-        if LREL_RE.is_match(line) {
-            // push old line to the Line vector and prepare new line
-            lines_vec.push(current_line);
-            let cap = LREL_RE.captures(line).unwrap();
-            let line_start = match cap.get(1).unwrap().as_str().parse() {
-                Ok(x) => x,
-                Err(_) => {
-                    bail!(ErrorKind::ValueError(line_count, "line start"));
-                }
-            };
-            let line_rel = match cap.get(2).unwrap().as_str().parse() {
-                Ok(x) => x,
-                Err(_) => {
-                    bail!(ErrorKind::ValueError(line_count, "line rel"));
-                }
-            };
-            current_line = Line {
-                start: line_start,
-                rel: Some(line_rel),
-                notes: Vec::new(),
-            };
+        // continued header values were already joined with their tag by the header parser
+        if in_header
+            && options.header_continuation
+            && line_count > 1
+            && !line.starts_with('#')
+            && !is_body_start(line)
+        {
             continue;
         }
-
-        if DUET_RE.is_match(line) {
-            let cap = DUET_RE.captures(line).unwrap();
-            let note = match cap.get(1).unwrap().as_str().parse() {
-                Ok(x) => {
-                    if x >= 1 && x <= 3 {
-                        Note::PlayerChange { player: x }
-                    } else {
-                        bail!(ErrorKind::ValueError(line_count, "player change"));
+        let body_line = parse_body_line(line, line_count, options);
+        // a body that starts with a line break instead of a note is most likely corrupted
+        if in_header
+            && matches!(
+                body_line,
+                Ok(BodyLine::LineBreak(_)) | Ok(BodyLine::BareLineBreak)
+            )
+        {
+            if let Some(ref mut warnings) = warnings {
+                warnings.push(Warning::LeadingLineBreak { line: line_count });
+            }
+        }
+        match body_line {
+            // lines starting with # are header tags until the first other line
+            Ok(BodyLine::Hash(text)) => {
+                if !in_header {
+                    comments.push((line_count, String::from(text)));
+                    if let Some(ref mut warnings) = warnings {
+                        warnings.push(Warning::CommentLine { line: line_count });
                     }
                 }
-                Err(_) => {
-                    bail!(ErrorKind::ValueError(line_count, "player change"));
+                continue;
+            }
+            // stop parsing after end symbol
+            Ok(BodyLine::End) => {
+                lines_vec.push(current_line);
+                return Ok((lines_vec, comments));
+            }
+            Ok(BodyLine::Note(note)) => current_line.notes.push(note),
+            // push old line to the Line vector and continue with the new line, a line break
+            // before the first note replaces the empty line instead
+            Ok(BodyLine::LineBreak(new_line)) => {
+                if !lines_vec.is_empty() || !current_line.notes.is_empty() {
+                    lines_vec.push(current_line);
                 }
-            };
-            current_line.notes.push(note);
-            continue;
+                current_line = new_line;
+            }
+            // a line break without start begins where the previous note ends
+            Ok(BodyLine::BareLineBreak) => {
+                let start = current_line
+                    .notes
+                    .iter()
+                    .rev()
+                    .find_map(|note| Some(note.start()? + note.duration()?))
+                    .unwrap_or(current_line.start);
+                if !lines_vec.is_empty() || !current_line.notes.is_empty() {
+                    lines_vec.push(current_line);
+                }
+                current_line = Line {
+                    start,
+                    rel: None,
+                    notes: Vec::new(),
+                };
+            }
+            Err(e) => {
+                let skip = options.on_unknown_note == UnknownNotePolicy::Skip
+                    && matches!(*e.kind(), ErrorKind::UnknownNoteType(..));
+                if !skip {
+                    on_error(e)?;
+                } else if let Some(ref mut warnings) = warnings {
+                    warnings.push(Warning::UnknownNoteType { line: line_count });
+                }
+            }
+        }
+        in_header = false;
+    }
+    bail!(ErrorKind::MissingEndIndicator);
+}
+
+// parses a single line of the body of a song
+fn parse_body_line<'a>(
+    line: &'a str,
+    line_count: u32,
+    options: &ParseOptions,
+) -> Result<BodyLine<'a>> {
+    // a stray carriage return might survive if lines end with \r\r\n
+    let line = line.trim_end_matches('\r');
+    let first_char = match line.chars().next() {
+        Some(x) => x,
+        None => bail!(ErrorKind::ParserFailure(line_count, excerpt(line))),
+    };
+
+    // header tag or comment
+    if first_char == '#' {
+        return Ok(BodyLine::Hash(&line[1..]));
+    }
+
+    // not implemented
+    if first_char == 'B' {
+        bail!(ErrorKind::NotImplemented(line_count, "variable bpm"));
+    }
+
+    // end symbol
+    if first_char == 'E' {
+        return Ok(BodyLine::End);
+    }
+
+    // current line is a note
+    if NOTE_RE.is_match(line) {
+        let cap = NOTE_RE.captures(line).unwrap();
+
+        let note_start = parse_group(&cap, 2, line, line_count, "note start")?;
+        let note_duration: Beat = parse_group(&cap, 3, line, line_count, "note duration")?;
+        if note_duration < 0 as Beat {
+            bail!(ErrorKind::ValueError(
+                line_count,
+                "note duration",
+                excerpt(line),
+                Some(cap.get(3).unwrap().start())
+            ));
+        }
+        let note_pitch = parse_group(&cap, 4, line, line_count, "note pitch")?;
+        if let Some(ref range) = options.pitch_range {
+            if !range.contains(&note_pitch) {
+                bail!(ErrorKind::ValueError(
+                    line_count,
+                    "note pitch",
+                    excerpt(line),
+                    Some(cap.get(4).unwrap().start())
+                ));
+            }
+        }
+        let note_text = if options.trim_note_text {
+            cap.get(5).unwrap().as_str().trim_end()
         } else {
-            // unknown line
-            bail!(ErrorKind::ParserFailure(line_count));
+            cap.get(5).unwrap().as_str()
+        };
+
+        let kind = match options.note_types {
+            Some(ref note_types) => note_types.get(&first_char).copied(),
+            None => match first_char {
+                ':' => Some(NoteKind::Regular),
+                '*' => Some(NoteKind::Golden),
+                'F' => Some(NoteKind::Freestyle),
+                _ => None,
+            },
+        };
+        let note = match kind {
+            Some(NoteKind::Regular) => Note::Regular {
+                start: note_start,
+                duration: note_duration,
+                pitch: note_pitch,
+                text: String::from(note_text),
+            },
+            Some(NoteKind::Golden) => Note::Golden {
+                start: note_start,
+                duration: note_duration,
+                pitch: note_pitch,
+                text: String::from(note_text),
+            },
+            Some(NoteKind::Freestyle) => Note::Freestyle {
+                start: note_start,
+                duration: note_duration,
+                pitch: note_pitch,
+                text: String::from(note_text),
+            },
+            None => bail!(ErrorKind::UnknownNoteType(line_count, excerpt(line))),
+        };
+
+        return Ok(BodyLine::Note(note));
+    }
+
+    // current line is a line break
+    if LINE_RE.is_match(line) {
+        let cap = LINE_RE.captures(line).unwrap();
+        let line_start = parse_group(&cap, 1, line, line_count, "line start")?;
+        return Ok(BodyLine::LineBreak(Line {
+            start: line_start,
+            rel: None,
+            notes: Vec::new(),
+        }));
+    }
+
+    // current line is a relative line break
+    if LREL_RE.is_match(line) {
+        let cap = LREL_RE.captures(line).unwrap();
+        let line_start = parse_group(&cap, 1, line, line_count, "line start")?;
+        let line_rel = parse_group(&cap, 2, line, line_count, "line rel")?;
+        return Ok(BodyLine::LineBreak(Line {
+            start: line_start,
+            rel: Some(line_rel),
+            notes: Vec::new(),
+        }));
+    }
+
+    // current line is a line break without start
+    if line.trim_end() == "-" {
+        if options.bare_line_breaks {
+            return Ok(BodyLine::BareLineBreak);
         }
+        bail!(ErrorKind::ValueError(
+            line_count,
+            "line start",
+            excerpt(line),
+            Some(1)
+        ));
     }
-    if found_end_indicator {
-        Ok(lines_vec)
+
+    if DUET_RE.is_match(line) {
+        let cap = DUET_RE.captures(line).unwrap();
+        let player = parse_group(&cap, 1, line, line_count, "player change")?;
+        match Note::player_change(player) {
+            Some(note) => Ok(BodyLine::Note(note)),
+            None => bail!(ErrorKind::ValueError(
+                line_count,
+                "player change",
+                excerpt(line),
+                Some(cap.get(1).unwrap().start())
+            )),
+        }
     } else {
-        bail!(ErrorKind::MissingEndIndicator);
+        // unknown line
+        bail!(ErrorKind::ParserFailure(line_count, excerpt(line)));
     }
 }
@@ -1,6 +1,21 @@
+use crate::generator::{write_header, GenerateOptions};
 use std::collections::HashMap;
+use std::fmt::{self, Write};
 use std::path::PathBuf;
 
+/// The type of all beat values, i.e. note starts, note durations and line starts
+///
+/// This is `i32` by default. With the `decimal-beats` feature enabled it becomes `f64`, which
+/// allows parsing chart dialects that use fractional beats. The affected fields are
+/// `Line::start`, `Line::rel` and the `start` and `duration` of every sung `Note` variant.
+/// Pitches and player numbers stay `i32` in both modes. `Line`, `Note` and `SongStats` only
+/// implement `Eq` while the feature is disabled.
+#[cfg(not(feature = "decimal-beats"))]
+pub type Beat = i32;
+/// The type of all beat values (`f64`, because the `decimal-beats` feature is enabled)
+#[cfg(feature = "decimal-beats")]
+pub type Beat = f64;
+
 /// Describes the Header of an Ultrastar Song
 #[derive(PartialEq, Clone, Debug)]
 pub struct Header {
@@ -9,21 +24,32 @@ pub struct Header {
     pub artist: String,
     /// the title of the song
     pub title: String,
-    /// the beats per minute of the song
+    /// the beats per minute of the song, the parser only accepts finite positive values
     pub bpm: f32,
+    /// the tempo changes of a `#BPM` tag that lists several tempos, e.g. `#BPM:0 120, 64 140`,
+    /// as pairs of the beat the tempo starts at and the bpm, `bpm` is the first of them
+    ///
+    /// the timing functions only use `bpm` so far, like variable bpm lines (`B`) they are not
+    /// supported yet
+    pub bpm_changes: Option<Vec<(Beat, f32)>>,
     /// the path to the music file
     pub audio_path: std::string::String,
 
     // optional data from headers
-    /// the gap between the start of the audio file and the first note in milliseconds
+    /// the gap between the start of the audio file and the first note in milliseconds, the
+    /// parser only accepts finite values
     pub gap: Option<f32>,
     /// the path to the cover file of the song
     pub cover_path: Option<String>,
     /// the path to the background file of the song
     pub background_path: Option<String>,
-    /// the path to the video file of the song
+    /// the path to the video file of the song, it might be followed by parameters, see
+    /// `Header::video_spec`
     pub video_path: Option<String>,
-    /// the time offset of the video file to the audio file
+    /// the time offset of the video file to the audio file in seconds
+    ///
+    /// negative values are valid and mean that the video starts before the audio, infinite
+    /// values and NaN are rejected by the parser
     pub video_gap: Option<f32>,
     /// the genre of the song
     pub genre: Option<String>,
@@ -31,8 +57,22 @@ pub struct Header {
     pub edition: Option<String>,
     /// the language the song is in
     pub language: Option<String>,
-    /// the year the song is from
+    /// the year the song is from, the parser only accepts four digit years
     pub year: Option<u32>,
+    /// the number of beats per quarter note, used by some legacy players
+    ///
+    /// most modern files omit this, players then assume a resolution of 4
+    pub resolution: Option<i32>,
+    /// an offset in beats that is applied to the notes by some legacy players
+    ///
+    /// most modern files omit this
+    pub notes_gap: Option<i32>,
+    /// the encoding of the song file as given in the header, e.g. `UTF8` or `CP1252`
+    pub encoding: Option<String>,
+    /// the beat the medley version of the song starts at, from `#MEDLEYSTARTBEAT` or `#MEDLEY`
+    pub medley_start_beat: Option<i32>,
+    /// the beat the medley version of the song ends at, from `#MEDLEYENDBEAT` or `#MEDLEY`
+    pub medley_end_beat: Option<i32>,
 
     /* header fields todo
     // these are header fields parsed by ultrastar deluxe
@@ -40,20 +80,176 @@ pub struct Header {
     pub creator: Option<String>,  // CREATOR
     pub start: Option<f32>,       // START
     pub end: Option<i32>,         // END
-    pub resolution: Option<i32>,  // RESOLUTION
-    pub notes_gap: Option<i32>,   // NOTESGAP
-    pub encoding: Option<String>, // ENCODING
     pub preview_start: Option<i32>, // PREVIEWSTART
-    pub medley_start_beat: Option<i32>, // MEDLEYSTARTBEAT
-    pub medley_end_beat: Option<i32>,   // MEDLEYENDBEAT
     pub calc_medley: Option<Bool>,      // CALCMEDLEY
-    pub duet_singer_p1: Option<String>, // DUETSINGERP1 / P1
-    pub duet_singer_p2: Option<String>, // DUETSINGERP2 / P2:
     */
+    /// the name of the first singer of a duet
+    pub p1: Option<String>,
+    /// the name of the second singer of a duet
+    pub p2: Option<String>,
+    /// the name of the part that both singers of a duet sing together, i.e. the notes after a
+    /// `P3` player change
+    pub p3: Option<String>,
     /// is the timing format of the song relative
     pub relative: Option<bool>,
     /// a hashmap that contains all tags that are unknown to the parser
     pub unknown: Option<HashMap<String, String>>,
+    /// the original spelling of recognized tags that were not written in upper case, keyed by
+    /// the upper case tag name
+    pub tag_spelling: Option<HashMap<String, String>>,
+    /// the tags in the order they were found, recognized tags by their upper case name and
+    /// unknown tags by their key, only recorded if `ParseOptions::keep_tag_order` is set
+    pub tag_order: Option<Vec<String>>,
+}
+
+impl fmt::Display for Header {
+    /// formats the header as the block of `#TAG:value` lines the generator writes for it
+    ///
+    /// the tags are written in the recorded order if there is one, otherwise in a fixed order
+    /// where unknown tags are sorted by their key. The values are written as they are, as
+    /// returning an error would make `to_string` panic, use `generate_header_txt` to reject
+    /// values with line breaks
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_header(f, self, &GenerateOptions::default())
+    }
+}
+
+impl Header {
+    /// returns whether the header names a second singer with `#P2` or `#DUETSINGERP2`
+    ///
+    /// the lines of the song might still lack player changes, see `TXTSong::is_duet`
+    pub fn is_duet(&self) -> bool {
+        self.p2.is_some()
+    }
+
+    /// returns the length of a beat in milliseconds
+    ///
+    /// the beats of the notes are quarter beats of the bpm given in the header, so the bpm is
+    /// multiplied by 4 to get the number of beats per minute
+    pub fn ms_per_beat(&self) -> f32 {
+        60000.0 / (self.bpm * 4.0)
+    }
+
+    /// returns the time of a beat in milliseconds from the start of the music file
+    ///
+    /// the gap is added to the time, a missing gap is treated as 0
+    pub fn beat_to_ms(&self, beat: Beat) -> f32 {
+        self.gap.unwrap_or(0.0) + beat as f32 * self.ms_per_beat()
+    }
+
+    /// returns the location of the music file
+    pub fn audio_media(&self) -> MediaPath {
+        MediaPath::from(self.audio_path.as_ref())
+    }
+
+    /// returns the location of the cover file
+    pub fn cover_media(&self) -> Option<MediaPath> {
+        self.cover_path
+            .as_ref()
+            .map(|x| MediaPath::from(x.as_ref()))
+    }
+
+    /// returns the location of the background file
+    pub fn background_media(&self) -> Option<MediaPath> {
+        self.background_path
+            .as_ref()
+            .map(|x| MediaPath::from(x.as_ref()))
+    }
+
+    /// returns the location of the video file, parameters after the file are not included
+    pub fn video_media(&self) -> Option<MediaPath> {
+        self.video_spec().map(|x| MediaPath::from(x.file.as_ref()))
+    }
+
+    /// returns the video file and its parameters
+    pub fn video_spec(&self) -> Option<VideoSpec> {
+        self.video_path
+            .as_ref()
+            .map(|x| VideoSpec::from(x.as_ref()))
+    }
+}
+
+/// Describes where a media file referenced by the header is located
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum MediaPath {
+    /// a file on the local file system, a `file://` prefix is removed
+    Local(PathBuf),
+    /// a remote file referenced by its URL
+    Remote(String),
+}
+
+impl MediaPath {
+    /// returns whether the media file is located on the local file system
+    pub fn is_local(&self) -> bool {
+        match *self {
+            MediaPath::Local(_) => true,
+            MediaPath::Remote(_) => false,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for MediaPath {
+    /// classifies a path from the header as local or remote
+    fn from(path: &'a str) -> MediaPath {
+        if path_is_local(path) {
+            let path = path.strip_prefix("file://").unwrap_or(path);
+            MediaPath::Local(PathBuf::from(path))
+        } else {
+            MediaPath::Remote(String::from(path))
+        }
+    }
+}
+
+/// Describes the value of the `#VIDEO` tag, a file that might be followed by parameters
+///
+/// The parameters are separated from the file and each other by commas and written as
+/// `key=value`, e.g. `clip.mp4, co=cover.jpg`. If any part after the first comma is not of this
+/// form the whole value is treated as the file, as file names can contain commas.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct VideoSpec {
+    /// the path to the video file
+    pub file: String,
+    /// the parameters of the video, keyed by their name
+    pub params: HashMap<String, String>,
+    /// the parameters as they are written in the tag, starting with the comma after the file, or
+    /// an empty string if there are none
+    pub raw_params: String,
+}
+
+impl<'a> From<&'a str> for VideoSpec {
+    /// splits the value of the `#VIDEO` tag into the file and its parameters
+    fn from(value: &'a str) -> VideoSpec {
+        let (file, raw_params) = match value.find(',') {
+            Some(comma) => value.split_at(comma),
+            None => (value, ""),
+        };
+        let params: Option<HashMap<String, String>> = raw_params
+            .split(',')
+            .skip(1)
+            .map(|part| {
+                let (key, value) = part.split_once('=')?;
+                Some((String::from(key.trim()), String::from(value.trim())))
+            })
+            .collect();
+        match params {
+            Some(params) => VideoSpec {
+                file: String::from(file.trim()),
+                params,
+                raw_params: String::from(raw_params),
+            },
+            None => VideoSpec {
+                file: String::from(value),
+                params: HashMap::new(),
+                raw_params: String::new(),
+            },
+        }
+    }
+}
+
+/// Returns whether the path references a local file.
+pub fn path_is_local(path: &str) -> bool {
+    // guess based on the occurence of a ://, but not a file://
+    !path.contains("://") || path.starts_with("file://")
 }
 
 /// Describes an Ultrastar song as the combination of its Header and its Lines
@@ -65,15 +261,458 @@ pub struct TXTSong {
     pub lines: Vec<Line>,
 }
 
+impl TXTSong {
+    /// returns whether the song is a duet
+    ///
+    /// the header and the lines can disagree, e.g. a header with `#P2` but no player changes in
+    /// the lines, so the song is a duet if either the header (see `Header::is_duet`) or any
+    /// `Note::PlayerChange` in the lines indicates it
+    pub fn is_duet(&self) -> bool {
+        self.header.is_duet()
+            || self
+                .lines
+                .iter()
+                .flat_map(|line| line.notes.iter())
+                .any(|note| note.player().is_some())
+    }
+
+    /// returns the number of players the song needs
+    ///
+    /// this is 2 for a duet and 1 for a solo song, a song is a duet if the header names a second
+    /// singer or the part both singers sing, or if the lines change to player 2 or to both
+    /// players (`P3`), see `Note::PlayerChange`
+    pub fn players(&self) -> i32 {
+        let header_players = if self.header.is_duet() || self.header.p3.is_some() {
+            2
+        } else {
+            1
+        };
+        self.notes_iter()
+            .filter_map(|note| note.player())
+            .fold(header_players, |players, player| {
+                players.max(player.clamp(1, 2))
+            })
+    }
+
+    /// returns whether the song uses relative timing, i.e. the header sets `#RELATIVE:YES`
+    ///
+    /// only then the `rel` values of the lines are meaningful and the starts of the notes are
+    /// relative to their line, see `to_absolute`
+    pub fn is_relative(&self) -> bool {
+        self.header.relative == Some(true)
+    }
+
+    /// returns the line that is active at the given beat
+    ///
+    /// This is the line with the greatest start that does not exceed the beat. The last line is
+    /// only active until its last note ends. `None` is returned if the beat is before the first
+    /// line or after the end of the last line.
+    ///
+    /// The lines have to be sorted by their start and use absolute timing, otherwise the result
+    /// is unspecified.
+    pub fn line_at_beat(&self, beat: Beat) -> Option<&Line> {
+        let index = self.lines.partition_point(|line| line.start <= beat);
+        let line = self.lines.get(index.checked_sub(1)?)?;
+        if index == self.lines.len() {
+            let (_, end) = beat_span(line.notes.iter())?;
+            if beat >= end {
+                return None;
+            }
+        }
+        Some(line)
+    }
+
+    /// returns the sung notes that are active at the given time in milliseconds
+    ///
+    /// The time is converted to a beat with the gap and bpm of the header, it can fall between
+    /// two beats. A note is active from its start up to but excluding its end, so nothing is
+    /// returned before the first note and between notes. Several notes are returned if they
+    /// overlap, e.g. in duets. This assumes that the song uses absolute timing.
+    pub fn notes_at_ms(&self, ms: f32) -> Vec<&Note> {
+        let beat = (ms - self.header.gap.unwrap_or(0.0)) / self.header.ms_per_beat();
+        self.notes_iter()
+            .filter(|note| match (note.start(), note.duration()) {
+                (Some(start), Some(duration)) => {
+                    start as f32 <= beat && beat < (start + duration) as f32
+                }
+                _ => false,
+            })
+            .collect()
+    }
+
+    /// returns the lines with a sung note that overlaps the beats from `from` to `to`
+    ///
+    /// Both bounds are inclusive. A note covers the beats from its start up to but excluding its
+    /// end, a note without duration only covers its start. Lines without sung notes are never
+    /// returned. This assumes that the song uses absolute timing.
+    pub fn lines_between(&self, from: Beat, to: Beat) -> Vec<&Line> {
+        self.lines
+            .iter()
+            .filter(|line| {
+                line.notes
+                    .iter()
+                    .any(|note| match (note.start(), note.duration()) {
+                        (Some(start), Some(duration)) => {
+                            start <= to && (start + duration > from || start >= from)
+                        }
+                        _ => false,
+                    })
+            })
+            .collect()
+    }
+
+    /// returns an iterator over all golden notes of the song
+    pub fn golden_notes(&self) -> impl Iterator<Item = &Note> {
+        self.notes_iter()
+            .filter(|note| matches!(*note, Note::Golden { .. }))
+    }
+
+    /// returns the summed up duration of all golden notes in beats
+    pub fn golden_beat_total(&self) -> Beat {
+        self.golden_notes()
+            .filter_map(|note| note.duration())
+            .fold(0 as Beat, |total, x| total + x)
+    }
+
+    /// returns whether the song contains at least one freestyle note
+    pub fn contains_freestyle(&self) -> bool {
+        self.notes_iter()
+            .any(|note| matches!(*note, Note::Freestyle { .. }))
+    }
+
+    /// returns whether every sung note of the song is a freestyle note
+    ///
+    /// a song without any sung notes is not freestyle only
+    pub fn is_freestyle_only(&self) -> bool {
+        let mut sung_notes = self
+            .notes_iter()
+            .filter(|note| note.start().is_some())
+            .peekable();
+        sung_notes.peek().is_some()
+            && sung_notes.all(|note| matches!(*note, Note::Freestyle { .. }))
+    }
+
+    /// consumes the song and returns the notes of all lines in order
+    ///
+    /// the header and the line boundaries are lost, player changes are kept as notes
+    pub fn into_notes(self) -> Vec<Note> {
+        self.lines.into_iter().flat_map(|line| line.notes).collect()
+    }
+
+    /// returns an iterator over the sung notes of one player
+    ///
+    /// the lines are walked in order, the notes before the first player change belong to player
+    /// 1 and every `Note::PlayerChange` sets the player of the notes after it. Notes after `P3`
+    /// are sung by both players and belong to player 1 and 2, player 3 returns only these notes.
+    pub fn notes_for_player(&self, player: i32) -> impl Iterator<Item = &Note> {
+        self.notes_iter()
+            .scan(1, |active, note| {
+                if let Some(changed) = note.player() {
+                    *active = changed;
+                }
+                Some((*active, note))
+            })
+            .filter(move |&(active, note)| {
+                (active == player || (active == 3 && (player == 1 || player == 2)))
+                    && note.player().is_none()
+            })
+            .map(|(_, note)| note)
+    }
+
+    // iterates over the notes of all lines
+    fn notes_iter(&self) -> impl Iterator<Item = &Note> {
+        self.lines.iter().flat_map(|line| line.notes.iter())
+    }
+
+    /// returns an iterator over all lines with their start and end in milliseconds
+    ///
+    /// A line spans from the start of its first sung note to the end of its last sung note. A
+    /// line without sung notes has a span of zero at the start of the line. This assumes that
+    /// the song uses absolute timing.
+    pub fn timed_lines(&self) -> impl Iterator<Item = (f32, f32, &Line)> {
+        self.lines.iter().map(move |line| {
+            let (start, end) = beat_span(line.notes.iter()).unwrap_or((line.start, line.start));
+            (
+                self.header.beat_to_ms(start),
+                self.header.beat_to_ms(end),
+                line,
+            )
+        })
+    }
+
+    /// returns the first and the last beat of the song that is sung
+    ///
+    /// this is the smallest start and the greatest end of all sung notes, `None` is returned if
+    /// the song has no sung notes
+    pub fn beat_range(&self) -> Option<(Beat, Beat)> {
+        beat_span(self.notes_iter())
+    }
+
+    /// returns the smallest start of all sung notes or 0 if the song has no sung notes
+    pub fn first_beat(&self) -> Beat {
+        self.beat_range().map_or(0 as Beat, |(first, _)| first)
+    }
+
+    /// returns the greatest end of all sung notes or 0 if the song has no sung notes
+    ///
+    /// useful as the length of the song in beats, e.g. for a progress bar
+    pub fn last_beat(&self) -> Beat {
+        self.beat_range().map_or(0 as Beat, |(_, last)| last)
+    }
+
+    /// returns statistics about the notes and lines of the song
+    pub fn stats(&self) -> SongStats {
+        let mut stats = SongStats {
+            notes: 0,
+            golden_notes: 0,
+            freestyle_notes: 0,
+            lines: self.lines.len(),
+            player_changes: 0,
+            lowest_pitch: None,
+            highest_pitch: None,
+            sung_beats: 0 as Beat,
+        };
+        for note in self.lines.iter().flat_map(|line| line.notes.iter()) {
+            match *note {
+                Note::Golden { .. } => stats.golden_notes += 1,
+                Note::Freestyle { .. } => stats.freestyle_notes += 1,
+                Note::PlayerChange { .. } => {
+                    stats.player_changes += 1;
+                    continue;
+                }
+                Note::Regular { .. } => (),
+            }
+            stats.notes += 1;
+            if let Some(pitch) = note.pitch() {
+                stats.lowest_pitch = Some(stats.lowest_pitch.map_or(pitch, |x| x.min(pitch)));
+                stats.highest_pitch = Some(stats.highest_pitch.map_or(pitch, |x| x.max(pitch)));
+            }
+            if let Some(duration) = note.duration() {
+                stats.sung_beats += duration;
+            }
+        }
+        stats
+    }
+
+    /// returns the lowest, highest, mean and median pitch of the sung notes
+    ///
+    /// freestyle notes are left out, as their pitch is not scored, `None` is returned if there are
+    /// no other sung notes. Every note counts once, regardless of its duration.
+    pub fn pitch_stats(&self) -> Option<PitchStats> {
+        let mut pitches: Vec<i32> = self
+            .notes_iter()
+            .filter(|note| !matches!(note, Note::Freestyle { .. }))
+            .filter_map(Note::pitch)
+            .collect();
+        if pitches.is_empty() {
+            return None;
+        }
+        pitches.sort_unstable();
+        let count = pitches.len();
+        let middle = count / 2;
+        let median = if count.is_multiple_of(2) {
+            ((f64::from(pitches[middle - 1]) + f64::from(pitches[middle])) / 2.0) as f32
+        } else {
+            pitches[middle] as f32
+        };
+        Some(PitchStats {
+            min: pitches[0],
+            max: pitches[count - 1],
+            mean: pitches.iter().map(|&x| x as f32).sum::<f32>() / count as f32,
+            median,
+        })
+    }
+
+    /// returns a rough estimate of how hard the song is to sing
+    ///
+    /// The note density (sung notes per second between the start of the first and the end of
+    /// the last sung note) and the pitch range each score 0 points below their medium threshold,
+    /// 1 point below their hard threshold and 2 points above it. A share of golden notes of at
+    /// least `Difficulty::GOLDEN_SHARE_HARD` scores another point. Up to 1 point is easy, up to
+    /// 3 points medium and anything above hard. A song without sung notes is easy. This assumes
+    /// that the song uses absolute timing.
+    pub fn difficulty_estimate(&self) -> Difficulty {
+        let stats = self.stats();
+        let (first, last) = match self.beat_range() {
+            Some(x) => x,
+            None => return Difficulty::Easy,
+        };
+        let seconds = (last - first) as f32 * self.header.ms_per_beat() / 1000.0;
+        let density = if seconds > 0.0 {
+            stats.notes as f32 / seconds
+        } else {
+            0.0
+        };
+        let pitch_range = match (stats.lowest_pitch, stats.highest_pitch) {
+            (Some(lowest), Some(highest)) => highest.saturating_sub(lowest),
+            _ => 0,
+        };
+        let golden_share = stats.golden_notes as f32 / stats.notes as f32;
+
+        let mut points = 0;
+        if density >= Difficulty::DENSITY_HARD {
+            points += 2;
+        } else if density >= Difficulty::DENSITY_MEDIUM {
+            points += 1;
+        }
+        if pitch_range >= Difficulty::PITCH_RANGE_HARD {
+            points += 2;
+        } else if pitch_range >= Difficulty::PITCH_RANGE_MEDIUM {
+            points += 1;
+        }
+        if golden_share >= Difficulty::GOLDEN_SHARE_HARD {
+            points += 1;
+        }
+        match points {
+            0..=1 => Difficulty::Easy,
+            2..=3 => Difficulty::Medium,
+            _ => Difficulty::Hard,
+        }
+    }
+
+    /// returns a fingerprint of the musical content of the song, e.g. to find duplicates
+    ///
+    /// The hash covers the title, the artist and the bpm of the header and every line with its
+    /// start, its `rel` value and all of its notes in order, including player changes. All other
+    /// header fields, e.g. the gap, the media paths and unknown tags, are left out, so songs that
+    /// only differ in them have the same hash. The hash is computed with 64 bit FNV-1a over the
+    /// text form of these values, it does not change between runs or versions of Rust.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Fnv1a(0xcbf2_9ce4_8422_2325);
+        write!(
+            hasher,
+            "{}\n{}\n{}\n",
+            self.header.title, self.header.artist, self.header.bpm
+        )
+        .expect("hashing can not fail");
+        for line in self.lines.iter() {
+            match line.rel {
+                Some(rel) => writeln!(hasher, "- {} {}", line.start, rel),
+                None => writeln!(hasher, "- {}", line.start),
+            }
+            .expect("hashing can not fail");
+            for note in line.notes.iter() {
+                writeln!(hasher, "{}", note).expect("hashing can not fail");
+            }
+        }
+        hasher.0
+    }
+}
+
+// 64 bit FNV-1a hash that is fed through fmt::Write
+struct Fnv1a(u64);
+
+impl fmt::Write for Fnv1a {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(())
+    }
+}
+
+// returns the smallest start and the greatest end of the sung notes
+fn beat_span<'a>(notes: impl Iterator<Item = &'a Note>) -> Option<(Beat, Beat)> {
+    let mut span: Option<(Beat, Beat)> = None;
+    for note in notes {
+        if let (Some(start), Some(duration)) = (note.start(), note.duration()) {
+            let end = start + duration;
+            span = Some(match span {
+                Some((first, last)) => (
+                    if start < first { start } else { first },
+                    if end > last { end } else { last },
+                ),
+                None => (start, end),
+            });
+        }
+    }
+    span
+}
+
+/// Describes statistics about a song, see `TXTSong::stats`
+#[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(not(feature = "decimal-beats"), derive(Eq))]
+pub struct SongStats {
+    /// the number of sung notes, including golden and freestyle notes
+    pub notes: usize,
+    /// the number of golden notes
+    pub golden_notes: usize,
+    /// the number of freestyle notes
+    pub freestyle_notes: usize,
+    /// the number of lines
+    pub lines: usize,
+    /// the number of player changes
+    pub player_changes: usize,
+    /// the lowest pitch of all sung notes
+    pub lowest_pitch: Option<i32>,
+    /// the highest pitch of all sung notes
+    pub highest_pitch: Option<i32>,
+    /// the summed up duration of all sung notes in beats
+    pub sung_beats: Beat,
+}
+
+/// Describes the pitches of the sung notes of a song, see `TXTSong::pitch_stats`
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct PitchStats {
+    /// the lowest pitch
+    pub min: i32,
+    /// the highest pitch
+    pub max: i32,
+    /// the mean of all pitches
+    pub mean: f32,
+    /// the median of all pitches, the mean of the two middle pitches for an even number of notes
+    pub median: f32,
+}
+
+/// A rough estimate of how hard a song is to sing, see `TXTSong::difficulty_estimate`
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Difficulty {
+    /// few, slow notes in a small pitch range
+    Easy,
+    /// songs in between
+    Medium,
+    /// many fast notes, a large pitch range or many golden notes
+    Hard,
+}
+
+impl Difficulty {
+    /// the sung notes per second from which the density counts as medium
+    pub const DENSITY_MEDIUM: f32 = 2.0;
+    /// the sung notes per second from which the density counts as hard
+    pub const DENSITY_HARD: f32 = 3.5;
+    /// the pitch range in semitones from which the range counts as medium
+    pub const PITCH_RANGE_MEDIUM: i32 = 12;
+    /// the pitch range in semitones from which the range counts as hard
+    pub const PITCH_RANGE_HARD: i32 = 19;
+    /// the share of golden notes from which they make the song harder
+    pub const GOLDEN_SHARE_HARD: f32 = 0.1;
+}
+
+/// The kinds of sung notes, used to map the note type letters of a dialect to the notes
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum NoteKind {
+    /// a `Note::Regular`, written as `:`
+    Regular,
+    /// a `Note::Golden`, written as `*`
+    Golden,
+    /// a `Note::Freestyle`, written as `F`
+    Freestyle,
+}
+
 /// Describes the different types of notes the parser might encounter
+///
+/// The parser accepts any `i32` as pitch, including pitches far below C2, unless
+/// `ParseOptions::pitch_range` restricts them.
 #[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(not(feature = "decimal-beats"), derive(Eq))]
 pub enum Note {
     /// a regular note
     Regular {
         /// start of the note
-        start: i32,
+        start: Beat,
         /// duration of the note
-        duration: i32,
+        duration: Beat,
         /// pitch of the note (in semitones with C2 being 0)
         pitch: i32,
         /// text or syllable of the note
@@ -82,9 +721,9 @@ pub enum Note {
     /// a golden note (2x points)
     Golden {
         /// start of the note
-        start: i32,
+        start: Beat,
         /// duration of the note
-        duration: i32,
+        duration: Beat,
         /// pitch of the note (in semitones with C2 being 0)
         pitch: i32,
         /// text or syllable of the note
@@ -93,9 +732,9 @@ pub enum Note {
     /// a freestyle note (note that does not award points)
     Freestyle {
         /// start of the note
-        start: i32,
+        start: Beat,
         /// duration of the note
-        duration: i32,
+        duration: Beat,
         /// pitch of the note (in semitones with C2 being 0)
         pitch: i32, //pitch might not be needed but not including it might lose data from orig file
         /// text or syllable of the note
@@ -112,8 +751,47 @@ pub enum Note {
 }
 
 impl Note {
+    /// returns a regular note
+    pub fn regular(start: Beat, duration: Beat, pitch: i32, text: impl Into<String>) -> Note {
+        Note::Regular {
+            start,
+            duration,
+            pitch,
+            text: text.into(),
+        }
+    }
+
+    /// returns a golden note
+    pub fn golden(start: Beat, duration: Beat, pitch: i32, text: impl Into<String>) -> Note {
+        Note::Golden {
+            start,
+            duration,
+            pitch,
+            text: text.into(),
+        }
+    }
+
+    /// returns a freestyle note
+    pub fn freestyle(start: Beat, duration: Beat, pitch: i32, text: impl Into<String>) -> Note {
+        Note::Freestyle {
+            start,
+            duration,
+            pitch,
+            text: text.into(),
+        }
+    }
+
+    /// returns a player change, `None` is returned if the player is not 1, 2 or 3 (both)
+    pub fn player_change(player: i32) -> Option<Note> {
+        if (1..=3).contains(&player) {
+            Some(Note::PlayerChange { player })
+        } else {
+            None
+        }
+    }
+
     /// returns the start value of the note
-    pub fn start(&self) -> Option<i32> {
+    pub fn start(&self) -> Option<Beat> {
         match *self {
             Note::Regular { start, .. }
             | Note::Golden { start, .. }
@@ -123,7 +801,7 @@ impl Note {
     }
 
     /// returns the duration value of the note
-    pub fn duration(&self) -> Option<i32> {
+    pub fn duration(&self) -> Option<Beat> {
         match *self {
             Note::Regular { duration, .. }
             | Note::Golden { duration, .. }
@@ -161,13 +839,59 @@ impl Note {
     }
 }
 
+impl fmt::Display for Note {
+    /// formats the note as the single line the generator writes for it (without line break)
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Note::Regular {
+                start,
+                duration,
+                pitch,
+                ref text,
+            } => write!(f, ": {} {} {} {}", start, duration, pitch, text),
+            Note::Golden {
+                start,
+                duration,
+                pitch,
+                ref text,
+            } => write!(f, "* {} {} {} {}", start, duration, pitch, text),
+            Note::Freestyle {
+                start,
+                duration,
+                pitch,
+                ref text,
+            } => write!(f, "F {} {} {} {}", start, duration, pitch, text),
+            Note::PlayerChange { player } => write!(f, "P{}", player),
+        }
+    }
+}
+
+/// A comment from the body of a song, consisting of its line number and its text without the
+/// leading `#`
+pub type Comment = (u32, String);
+
 /// Describes a line or sentence that is made up of notes their syllables
 #[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(not(feature = "decimal-beats"), derive(Eq))]
 pub struct Line {
     /// the start of the line in beats
-    pub start: i32,
+    pub start: Beat,
     /// the second value needed for relative timing
-    pub rel: Option<i32>,
+    ///
+    /// only songs with `#RELATIVE:YES` may have it, the generator refuses to write it otherwise
+    pub rel: Option<Beat>,
     /// the notes the line contains
     pub notes: Vec<Note>,
 }
+
+impl Line {
+    /// returns whether the line contains no notes at all
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    /// returns whether the line contains player changes but no sung notes
+    pub fn is_player_change_only(&self) -> bool {
+        !self.notes.is_empty() && self.notes.iter().all(|note| note.player().is_some())
+    }
+}
@@ -0,0 +1,44 @@
+use crate::parser::{DUET_RE, LINE_RE, LREL_RE, NOTE_RE};
+use crate::structs::Note;
+
+// removes a trailing carriage return like the parser does
+fn trim_line(line: &str) -> &str {
+    line.trim_end_matches('\r')
+}
+
+/// Returns whether the line is a regular, golden or freestyle note, e.g. `: 0 4 59 Test`
+///
+/// The values are separated by whitespace, the text might be empty.
+pub fn is_note_line(line: &str) -> bool {
+    let line = trim_line(line);
+    NOTE_RE.is_match(line) && matches!(line.chars().next(), Some(':') | Some('*') | Some('F'))
+}
+
+/// Returns whether the line is a line break with a single value, e.g. `- 20`
+pub fn is_line_break(line: &str) -> bool {
+    LINE_RE.is_match(trim_line(line))
+}
+
+/// Returns whether the line is a line break with a second value for relative timing, e.g.
+/// `- 20 24`
+pub fn is_relative_line_break(line: &str) -> bool {
+    let line = trim_line(line);
+    !LINE_RE.is_match(line) && LREL_RE.is_match(line)
+}
+
+/// Returns whether the line changes the player of a duet, e.g. `P2`
+///
+/// Only the players 1, 2 and 3 (both) are valid.
+pub fn is_player_change(line: &str) -> bool {
+    match DUET_RE.captures(trim_line(line)) {
+        Some(cap) => cap
+            .get(1)
+            .unwrap()
+            .as_str()
+            .parse()
+            .ok()
+            .and_then(Note::player_change)
+            .is_some(),
+        None => false,
+    }
+}
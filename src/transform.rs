@@ -0,0 +1,426 @@
+use crate::structs::*;
+use std::cmp::Ordering;
+
+/// Shifts the timing of the whole song by adjusting its gap, the beats are not touched
+///
+/// # Arguments
+/// * header - the Header struct of the song, a missing gap is treated as 0
+/// * delta_ms - the offset in milliseconds, positive values make the notes start later
+///
+pub fn shift_gap(header: &mut Header, delta_ms: f32) {
+    header.gap = Some(header.gap.unwrap_or(0.0) + delta_ms);
+}
+
+/// Removes the metadata that is not needed to play the song, e.g. before sharing it
+///
+/// Only `unknown` is cleared. Tags like `#CREATOR`, `#COMMENT`, `#SOURCE` or `#TAGS` have no
+/// field of their own and are stored there, so they are removed with every other unknown tag.
+/// All recognized tags are kept, including the title, artist, BPM, gap, the audio and media
+/// paths and the names of the singers.
+///
+/// # Arguments
+/// * header - the Header struct of the song
+///
+pub fn strip_metadata(header: &mut Header) {
+    header.unknown = None;
+}
+
+/// Shifts all notes and line breaks of a song by a number of beats
+///
+/// The first line is left untouched if it starts at beat 0, because it is not started by a line
+/// break in the song file. Player changes have no start and are not changed. This assumes that
+/// the song uses absolute timing.
+///
+/// # Arguments
+/// * lines - the lines of the song
+/// * delta - the number of beats to shift by, positive values make the notes start later
+///
+pub fn shift_beats(lines: &mut [Line], delta: Beat) {
+    for (index, line) in lines.iter_mut().enumerate() {
+        if index != 0 || line.start != 0 as Beat {
+            line.start += delta;
+        }
+        for note in line.notes.iter_mut() {
+            match *note {
+                Note::Regular { ref mut start, .. }
+                | Note::Golden { ref mut start, .. }
+                | Note::Freestyle { ref mut start, .. } => *start += delta,
+                Note::PlayerChange { .. } => (),
+            }
+        }
+    }
+}
+
+/// Calls the function with every note of the song, including player changes, in order
+///
+/// # Arguments
+/// * lines - the lines of the song
+/// * f - the function that may change the notes
+///
+pub fn visit_notes_mut<F: FnMut(&mut Note)>(lines: &mut [Line], f: F) {
+    lines
+        .iter_mut()
+        .flat_map(|line| line.notes.iter_mut())
+        .for_each(f);
+}
+
+/// Calls the function with every line of the song in order
+///
+/// # Arguments
+/// * lines - the lines of the song
+/// * f - the function that may change the lines
+///
+pub fn visit_lines_mut<F: FnMut(&mut Line)>(lines: &mut [Line], f: F) {
+    lines.iter_mut().for_each(f);
+}
+
+/// Merges two songs into a duet
+///
+/// The header of the first song is used for the duet. The singer names are taken from the titles
+/// of the songs if the header of the first song does not name them already.
+///
+/// The lines of both songs are kept as they are and written one after the other, each part is
+/// started by a player change. The notes of the second part are not retimed, so both songs have
+/// to use the BPM and GAP of the first song. Because each player gets their own lines, songs of
+/// different length need no alignment, the shorter part just ends earlier.
+///
+/// # Arguments
+/// * p1 - the song of the first singer
+/// * p2 - the song of the second singer
+///
+pub fn merge_to_duet(p1: &TXTSong, p2: &TXTSong) -> TXTSong {
+    let mut header = p1.header.clone();
+    if header.p1.is_none() {
+        header.p1 = Some(p1.header.title.clone());
+    }
+    if header.p2.is_none() {
+        header.p2 = Some(p2.header.title.clone());
+    }
+
+    let mut lines = p1.lines.clone();
+    if lines.is_empty() {
+        lines.push(Line {
+            start: 0 as Beat,
+            rel: None,
+            notes: Vec::new(),
+        });
+    }
+    lines[0].notes.insert(0, Note::PlayerChange { player: 1 });
+
+    // the second part continues in the last line of the first part like in a duet file
+    let mut p2_lines = p2.lines.iter();
+    let last_line = lines.last_mut().unwrap();
+    last_line.notes.push(Note::PlayerChange { player: 2 });
+    if let Some(first_line) = p2.lines.first() {
+        if first_line.start == 0 as Beat {
+            last_line.notes.extend(first_line.notes.iter().cloned());
+            p2_lines.next();
+        }
+    }
+    lines.extend(p2_lines.cloned());
+
+    TXTSong { header, lines }
+}
+
+/// Shortens notes that overlap the next note of their line
+///
+/// Only durations are changed, the starts of the notes stay the same. A note is never shortened
+/// below a duration of 1, so it might still overlap if the next note starts at the same beat.
+/// A player change separates the parts of a duet, the last notes before it are not limited by
+/// the notes after it. Notes that start after the next note of their line are not sorted and are
+/// left as they are, use `sort_lines` first.
+///
+/// # Arguments
+/// * lines - the lines of the song
+///
+pub fn clamp_overlaps(lines: &mut [Line]) {
+    for line in lines.iter_mut() {
+        let mut next_start = None;
+        // walk backwards, so the start of the next sung note is always known
+        for note in line.notes.iter_mut().rev() {
+            match *note {
+                Note::Regular {
+                    start,
+                    ref mut duration,
+                    ..
+                }
+                | Note::Golden {
+                    start,
+                    ref mut duration,
+                    ..
+                }
+                | Note::Freestyle {
+                    start,
+                    ref mut duration,
+                    ..
+                } => {
+                    if let Some(next_start) = next_start {
+                        // a next note that starts earlier is out of order, not overlapping
+                        if start + *duration > next_start && next_start >= start {
+                            *duration = next_start - start;
+                            if *duration < 1 as Beat {
+                                *duration = 1 as Beat;
+                            }
+                        }
+                    }
+                    next_start = Some(start);
+                }
+                // the part of the next player does not limit the notes before it
+                Note::PlayerChange { .. } => next_start = None,
+            }
+        }
+    }
+}
+
+/// Merges notes of a line that follow each other without a gap and have the same pitch
+///
+/// Two notes are merged if the second one starts where the first one ends and both have the same
+/// pitch and kind, e.g. both are golden. The merged note keeps the start of the first note, its
+/// duration is the sum of both and its text is the text of both joined. A player change between
+/// two notes keeps them apart.
+///
+/// # Arguments
+/// * lines - the lines of the song
+///
+pub fn merge_adjacent(lines: &mut [Line]) {
+    for line in lines.iter_mut() {
+        let mut merged: Vec<Note> = Vec::with_capacity(line.notes.len());
+        for note in std::mem::take(&mut line.notes) {
+            if let Some(previous) = merged.last_mut() {
+                if merge_notes(previous, &note) {
+                    continue;
+                }
+            }
+            merged.push(note);
+        }
+        line.notes = merged;
+    }
+}
+
+// appends the next note to the previous one if they can be merged and returns whether they were
+fn merge_notes(previous: &mut Note, next: &Note) -> bool {
+    if std::mem::discriminant(previous) != std::mem::discriminant(next) {
+        return false;
+    }
+    match *previous {
+        Note::Regular {
+            start,
+            ref mut duration,
+            pitch,
+            ref mut text,
+        }
+        | Note::Golden {
+            start,
+            ref mut duration,
+            pitch,
+            ref mut text,
+        }
+        | Note::Freestyle {
+            start,
+            ref mut duration,
+            pitch,
+            ref mut text,
+        } => {
+            if next.start() != Some(start + *duration) || next.pitch() != Some(pitch) {
+                return false;
+            }
+            *duration += next.duration().unwrap_or(0 as Beat);
+            text.push_str(next.text().unwrap_or(""));
+            true
+        }
+        Note::PlayerChange { .. } => false,
+    }
+}
+
+/// Folds the pitch of every sung note into a single octave, i.e. the range 0..12
+///
+/// This is lossy, the octave of the notes can not be restored afterwards. It is meant for
+/// displays that only show the pitch class of the notes. Player changes are not changed.
+///
+/// # Arguments
+/// * lines - the lines of the song
+///
+pub fn normalize_pitch_octave(lines: &mut [Line]) {
+    for note in lines.iter_mut().flat_map(|line| line.notes.iter_mut()) {
+        match *note {
+            Note::Regular { ref mut pitch, .. }
+            | Note::Golden { ref mut pitch, .. }
+            | Note::Freestyle { ref mut pitch, .. } => *pitch = pitch.rem_euclid(12),
+            Note::PlayerChange { .. } => (),
+        }
+    }
+}
+
+/// Turns every golden note into a regular note, e.g. for an easy mode without bonus points
+///
+/// The start, duration, pitch and text of the notes are kept.
+///
+/// # Arguments
+/// * lines - the lines of the song
+///
+pub fn remove_golden(lines: &mut [Line]) {
+    to_regular(lines, |note| matches!(*note, Note::Golden { .. }));
+}
+
+/// Turns every freestyle note into a regular note, so it awards points
+///
+/// The start, duration, pitch and text of the notes are kept.
+///
+/// # Arguments
+/// * lines - the lines of the song
+///
+pub fn remove_freestyle(lines: &mut [Line]) {
+    to_regular(lines, |note| matches!(*note, Note::Freestyle { .. }));
+}
+
+// turns the sung notes that match the predicate into regular notes
+fn to_regular<F: Fn(&Note) -> bool>(lines: &mut [Line], predicate: F) {
+    for note in lines.iter_mut().flat_map(|line| line.notes.iter_mut()) {
+        if !predicate(note) {
+            continue;
+        }
+        match *note {
+            Note::Regular {
+                start,
+                duration,
+                pitch,
+                ref mut text,
+            }
+            | Note::Golden {
+                start,
+                duration,
+                pitch,
+                ref mut text,
+            }
+            | Note::Freestyle {
+                start,
+                duration,
+                pitch,
+                ref mut text,
+            } => {
+                let text = std::mem::take(text);
+                *note = Note::Regular {
+                    start,
+                    duration,
+                    pitch,
+                    text,
+                };
+            }
+            Note::PlayerChange { .. } => (),
+        }
+    }
+}
+
+/// Returns a copy of the song that uses absolute timing
+///
+/// If the song uses relative timing, the starts of all line breaks and notes are converted to
+/// absolute beats. A line break without a second value moves the following notes by its start.
+/// The `rel` values of all lines are removed and so is the `#RELATIVE` tag, songs that already
+/// use absolute timing keep their beats.
+///
+/// # Arguments
+/// * song - the song to convert
+///
+pub fn to_absolute(song: &TXTSong) -> TXTSong {
+    let mut song = song.clone();
+    let relative = song.is_relative();
+    song.header.relative = None;
+    if let Some(ref mut spelling) = song.header.tag_spelling {
+        spelling.remove("RELATIVE");
+    }
+
+    // the beat the notes of the current line are relative to
+    let mut base = 0 as Beat;
+    for (index, line) in song.lines.iter_mut().enumerate() {
+        if relative {
+            if index != 0 || line.start != 0 as Beat {
+                let start = line.start;
+                line.start += base;
+                base += line.rel.unwrap_or(start);
+            }
+            for note in line.notes.iter_mut() {
+                match *note {
+                    Note::Regular { ref mut start, .. }
+                    | Note::Golden { ref mut start, .. }
+                    | Note::Freestyle { ref mut start, .. } => *start += base,
+                    Note::PlayerChange { .. } => (),
+                }
+            }
+        }
+        line.rel = None;
+    }
+    song
+}
+
+/// Snaps the start and duration of every sung note to a grid of beats
+///
+/// Starts and durations are rounded to the nearest multiple of the grid, but durations are never
+/// shorter than 1. This is lossy and might make notes overlap, use `clamp_overlaps` afterwards to
+/// fix that. Line breaks and player changes are not changed.
+///
+/// # Arguments
+/// * lines - the lines of the song
+/// * grid - the size of the grid in beats, e.g. 2 to only allow even beats
+///
+/// # Panics
+/// if the grid is not positive
+///
+pub fn quantize(lines: &mut [Line], grid: Beat) {
+    assert!(grid > 0 as Beat, "the grid has to be positive");
+    // the casts are only unnecessary with decimal-beats, where Beat already is f64
+    #[allow(clippy::unnecessary_cast)]
+    let snap = |x: Beat| ((x as f64 / grid as f64).round() * grid as f64) as Beat;
+    for note in lines.iter_mut().flat_map(|line| line.notes.iter_mut()) {
+        match *note {
+            Note::Regular {
+                ref mut start,
+                ref mut duration,
+                ..
+            }
+            | Note::Golden {
+                ref mut start,
+                ref mut duration,
+                ..
+            }
+            | Note::Freestyle {
+                ref mut start,
+                ref mut duration,
+                ..
+            } => {
+                *start = snap(*start);
+                *duration = snap(*duration);
+                if *duration < 1 as Beat {
+                    *duration = 1 as Beat;
+                }
+            }
+            Note::PlayerChange { .. } => (),
+        }
+    }
+}
+
+/// Sorts the lines of a song and the notes of every line by their start
+///
+/// The sorting is stable. The first line stays first if it starts at beat 0, because it is not
+/// started by a line break in the song file. Player changes keep their position, only the notes
+/// between them are sorted. This assumes that the song uses absolute timing, use `to_absolute`
+/// first for songs with relative timing. The parts of a duet that start over at the beginning of
+/// the song are mixed by this.
+///
+/// # Arguments
+/// * song - the song to sort
+///
+pub fn sort_lines(song: &mut TXTSong) {
+    let by_start = |a: &Option<Beat>, b: &Option<Beat>| a.partial_cmp(b).unwrap_or(Ordering::Equal);
+
+    let lines = &mut song.lines;
+    let skip = match lines.first() {
+        Some(line) if line.start == 0 as Beat => 1,
+        _ => 0,
+    };
+    lines[skip..].sort_by(|a, b| by_start(&Some(a.start), &Some(b.start)));
+    for line in lines.iter_mut() {
+        for part in line.notes.split_mut(|note| note.player().is_some()) {
+            part.sort_by(|a, b| by_start(&a.start(), &b.start()));
+        }
+    }
+}
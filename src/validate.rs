@@ -0,0 +1,156 @@
+use crate::structs::*;
+use crate::warnings::Warning;
+use std::fmt;
+
+// the gap below which it might be given in seconds
+const SMALL_GAP_MS: f32 = 100.0;
+// the time after which the first note starts late
+const LATE_NOTE_MS: f32 = 1000.0;
+// the gap above which it is longer than almost every song
+const LARGE_GAP_MS: f32 = 600_000.0;
+
+/// Describes a problem in the timing of a song, see `validate`
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Issue {
+    /// a sung note starts before the previous sung note of the same line ends
+    OverlappingNotes {
+        /// the index of the line
+        line: usize,
+        /// the index of the note in the line
+        note: usize,
+    },
+    /// a line starts before the last sung note of an earlier line ends
+    LineOutOfOrder {
+        /// the index of the line that starts too early
+        line: usize,
+        /// the index of the line with the last sung note before it
+        previous: usize,
+    },
+    /// the song has a part for player 2 but none for this one, e.g. it changes to `P2` before
+    /// its first sung note
+    MissingPlayer {
+        /// the player without a part
+        player: i32,
+    },
+}
+
+impl fmt::Display for Issue {
+    /// formats the issue as a message for the user
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Issue::OverlappingNotes { line, note } => write!(
+                f,
+                "note {} of line {} starts before the previous note ends",
+                note, line
+            ),
+            Issue::LineOutOfOrder { line, previous } => write!(
+                f,
+                "line {} starts before the end of line {}",
+                line, previous
+            ),
+            Issue::MissingPlayer { player } => write!(
+                f,
+                "there is no part for player {} before a higher player",
+                player
+            ),
+        }
+    }
+}
+
+/// Checks the timing of a song and returns the issues that were found
+///
+/// Within a line every sung note has to start after the previous sung note ends. Across lines
+/// every line has to start after the last sung note of the lines before it ends, this catches
+/// parts that were pasted out of order. The parts of a duet are checked independently, a player
+/// change starts over. The check across lines is skipped for songs that use relative timing, as
+/// their line starts are not comparable.
+///
+/// The player changes have to use all players from 1 up to the highest one, every player that is
+/// skipped is reported once as `Issue::MissingPlayer` after the timing issues. This catches duets
+/// that start with `P2`. Like in `TXTSong::notes_for_player` the notes before the first player
+/// change belong to player 1. A `P3` part is sung by both players and needs no part for player 2.
+///
+/// # Arguments
+/// * song - the song to check
+///
+pub fn validate(song: &TXTSong) -> Vec<Issue> {
+    let relative = song.is_relative();
+    let mut issues = Vec::new();
+    // the index of the line and the end of the last sung note
+    let mut previous_end: Option<(usize, Beat)> = None;
+    let mut players: Vec<i32> = Vec::new();
+    let mut changed_player = false;
+
+    for (line_index, line) in song.lines.iter().enumerate() {
+        if let Some((previous, end)) = previous_end {
+            if !relative && line_index != 0 && line.start < end {
+                issues.push(Issue::LineOutOfOrder {
+                    line: line_index,
+                    previous,
+                });
+            }
+        }
+
+        let mut line_end: Option<Beat> = None;
+        for (note_index, note) in line.notes.iter().enumerate() {
+            let (start, duration) = match (note.start(), note.duration()) {
+                (Some(start), Some(duration)) => (start, duration),
+                // a player change starts a new part
+                _ => {
+                    players.extend(note.player().filter(|&player| player != 3));
+                    changed_player |= note.player().is_some();
+                    line_end = None;
+                    previous_end = None;
+                    continue;
+                }
+            };
+            // the notes before the first player change are sung by player 1
+            if !changed_player && !players.contains(&1) {
+                players.push(1);
+            }
+            if line_end.is_some_and(|end| start < end) {
+                issues.push(Issue::OverlappingNotes {
+                    line: line_index,
+                    note: note_index,
+                });
+            }
+            line_end = Some(start + duration);
+            previous_end = Some((line_index, start + duration));
+        }
+    }
+
+    if let Some(&highest) = players.iter().max() {
+        issues.extend(
+            (1..highest)
+                .filter(|player| !players.contains(player))
+                .map(|player| Issue::MissingPlayer { player }),
+        );
+    }
+    issues
+}
+
+/// Checks the gap of a song for likely unit mistakes and returns a warning if one was found
+///
+/// This is a heuristic, so it only produces warnings. A gap below 100 ms (but not 0) is
+/// reported as `Warning::GapInSeconds` if the first sung note starts more than a second after
+/// the start of the music file. The gap usually marks the start of the singing, so a tiny gap
+/// before a late first note was most likely meant in seconds, e.g. `#GAP:12` for 12000 ms. A gap
+/// of more than 10 minutes is reported as `Warning::GapTooLarge`. Songs without a gap are never
+/// reported.
+///
+/// # Arguments
+/// * song - the song to check
+///
+pub fn check_gap(song: &TXTSong) -> Option<Warning> {
+    let gap = song.header.gap?;
+    if gap > LARGE_GAP_MS {
+        return Some(Warning::GapTooLarge);
+    }
+    if gap > 0.0 && gap < SMALL_GAP_MS {
+        let (first, _) = song.beat_range()?;
+        if song.header.beat_to_ms(first) > LATE_NOTE_MS {
+            return Some(Warning::GapInSeconds);
+        }
+    }
+    None
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+
+/// Describes a problem in a song that did not stop it from being parsed
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Warning {
+    /// a header tag without a value, it was ignored
+    EmptyTag {
+        /// the line of the tag
+        line: u32,
+        /// the tag as it was spelled in the song
+        tag: String,
+    },
+    /// a line in the header that starts with `#` but is not a valid tag, e.g. because it has no
+    /// colon, it was skipped
+    MalformedTag {
+        /// the line of the tag
+        line: u32,
+        /// the content of the line without the leading `#`
+        text: String,
+    },
+    /// a blank line in the body of the song, it was skipped
+    BlankLine {
+        /// the blank line
+        line: u32,
+    },
+    /// a comment in the body of the song, it was skipped
+    CommentLine {
+        /// the line of the comment
+        line: u32,
+    },
+    /// the body of the song starts with a line break instead of a note, this usually means that
+    /// the file is corrupted
+    LeadingLineBreak {
+        /// the line of the line break
+        line: u32,
+    },
+    /// a note of unknown type, it was skipped
+    UnknownNoteType {
+        /// the line of the note
+        line: u32,
+    },
+    /// the gap is below 100 ms while the first note starts after the first second, it was most
+    /// likely given in seconds instead of milliseconds, see `check_gap`
+    GapInSeconds,
+    /// the gap is longer than 10 minutes, see `check_gap`
+    GapTooLarge,
+    /// a media path that could not be canonicalized, it was kept as it is
+    UncanonicalizedPath {
+        /// the header tag of the path
+        tag: &'static str,
+        /// the path as it was given in the header
+        path: String,
+    },
+    /// bytes of the song file that could not be decoded, they were dropped
+    UndecodableBytes {
+        /// the number of dropped bytes
+        count: usize,
+        /// the byte offset of the first dropped byte in the (decompressed) file
+        offset: usize,
+    },
+}
+
+impl fmt::Display for Warning {
+    /// formats the warning as a message for the user
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::EmptyTag { line, ref tag } => {
+                write!(f, "empty {} tag ignored in line: {}", tag, line)
+            }
+            Warning::MalformedTag { line, ref text } => {
+                write!(f, "malformed tag {} ignored in line: {}", text, line)
+            }
+            Warning::BlankLine { line } => write!(f, "blank line skipped: {}", line),
+            Warning::CommentLine { line } => write!(f, "comment skipped in line: {}", line),
+            Warning::LeadingLineBreak { line } => {
+                write!(f, "the body starts with a line break in line: {}", line)
+            }
+            Warning::UnknownNoteType { line } => {
+                write!(f, "note of unknown type skipped in line: {}", line)
+            }
+            Warning::GapInSeconds => write!(f, "gap seems to be given in seconds"),
+            Warning::GapTooLarge => write!(f, "gap is longer than 10 minutes"),
+            Warning::UncanonicalizedPath { tag, ref path } => {
+                write!(f, "could not canonicalize {} path: {}", tag, path)
+            }
+            Warning::UndecodableBytes { count, offset } => write!(
+                f,
+                "{} undecodable bytes dropped, the first at offset: {}",
+                count, offset
+            ),
+        }
+    }
+}
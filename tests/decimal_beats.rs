@@ -0,0 +1,22 @@
+#![cfg(feature = "decimal-beats")]
+extern crate ultrastar_txt;
+
+use ultrastar_txt::*;
+
+#[test]
+fn decimal_beats() {
+    let txt = include_str!("txts/decimal_beats.txt");
+    let lines = parse_txt_lines_str(txt).unwrap();
+    assert_eq!(lines[0].notes[0].duration(), Some(4.5));
+    assert_eq!(lines[0].notes[1].start(), Some(4.5));
+    assert_eq!(lines[1].start, 8.5);
+}
+
+#[test]
+fn generate_and_reparse_decimal_beats() {
+    let txt = include_str!("txts/decimal_beats.txt");
+    let header = parse_txt_header_str(txt).unwrap();
+    let lines = parse_txt_lines_str(txt).unwrap();
+    let generated_txt = generate_song_txt(&header, &lines).unwrap();
+    assert_eq!(parse_txt_lines_str(generated_txt.as_ref()).unwrap(), lines);
+}
@@ -0,0 +1,316 @@
+#![cfg(feature = "file-support")]
+extern crate ultrastar_txt;
+
+use std::error::Error;
+use std::path::Path;
+use ultrastar_txt::*;
+
+#[test]
+fn utf8_bom() {
+    let song = parse_txt_song(txt_path("bom_utf8.txt")).unwrap();
+    assert_eq!(song.header.title, "Testsong");
+}
+
+#[test]
+fn media_base_directory() {
+    let song = parse_txt_song_with_base(txt_path("komma_in_float.txt"), txt_path("media")).unwrap();
+    let audio_path = txt_path("media/Testfile.mp3").canonicalize().unwrap();
+    assert_eq!(song.header.audio_path, audio_path.display().to_string());
+}
+
+#[test]
+fn song_directory_as_default_base() {
+    let song = parse_txt_song(txt_path("komma_in_float.txt")).unwrap();
+    let audio_path = txt_path("Testfile.mp3").canonicalize().unwrap();
+    assert_eq!(song.header.audio_path, audio_path.display().to_string());
+}
+
+#[test]
+fn header_error_message_contains_line() {
+    let err = parse_txt_song(txt_path("duplicate_header_bpm.txt")).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "header parsing error: additional BPM tag found in line: 6"
+    );
+    assert!(err.source().unwrap().is::<ultrastar_txt::parser::Error>());
+}
+
+#[test]
+fn lines_error_message_contains_line() {
+    let err = parse_txt_song(txt_path("garbage_line.txt")).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "lines parsing error: could not parse line 7: `Test`"
+    );
+    match *err.kind() {
+        ultrastar_txt::loader::ErrorKind::LinesParsingError(_) => (),
+        _ => panic!("unexpected error kind: {:?}", err),
+    }
+}
+
+#[test]
+fn format_error_with_file_and_line() {
+    let path = txt_path("garbage_line.txt");
+    let err = parse_txt_song(&path).unwrap_err();
+    assert_eq!(
+        format_error(&path, &err),
+        format!("{}:7: could not parse line 7: `Test`", path.display())
+    );
+
+    let path = txt_path("missing_essential_header.txt");
+    let err = parse_txt_song(&path).unwrap_err();
+    assert_eq!(
+        format_error(&path, &err),
+        format!("{}: essential header is missing", path.display())
+    );
+
+    let path = txt_path("no_such_file.txt");
+    let err = parse_txt_song(&path).unwrap_err();
+    assert_eq!(
+        format_error(&path, &err),
+        format!("{}: io error", path.display())
+    );
+}
+
+#[test]
+fn parse_bytes_with_detected_encoding() {
+    let bytes = include_bytes!("txts/komma_in_float.txt");
+    let song = parse_txt_song_bytes(bytes, None).unwrap();
+    assert_eq!(song.header.audio_path, "Testfile.mp3");
+    assert_eq!(song.lines[0].notes[0].text(), Some("Test"));
+}
+
+#[test]
+fn song_try_from_bytes() {
+    use std::convert::TryFrom;
+    let bytes = std::fs::read(txt_path("undetectable_encoding.txt")).unwrap();
+    let song = TXTSong::try_from(&bytes[..]).unwrap();
+    assert_eq!(song, parse_txt_song_bytes(&bytes, None).unwrap());
+    assert_eq!(song.lines[0].notes[0].text(), Some("œ„çÒ"));
+    assert!(TXTSong::try_from(&b"garbage"[..]).is_err());
+}
+
+#[test]
+fn parse_bytes_with_explicit_encoding() {
+    let bytes =
+        b"#TITLE:Caf\xe9\n#ARTIST:Testartist\n#MP3:Testfile.mp3\n#BPM:123\n: 0 4 59 Test\nE";
+    let song = parse_txt_song_bytes(bytes, Some("windows-1252")).unwrap();
+    assert_eq!(song.header.title, "Caf\u{e9}");
+}
+
+#[test]
+fn parse_bytes_with_unknown_encoding() {
+    let bytes = include_bytes!("txts/komma_in_float.txt");
+    let err = parse_txt_song_bytes(bytes, Some("no-such-encoding")).unwrap_err();
+    match *err.kind() {
+        ultrastar_txt::loader::ErrorKind::UnknownEncoding(ref label) => {
+            assert_eq!(label, "no-such-encoding")
+        }
+        _ => panic!("unexpected error kind: {:?}", err),
+    }
+}
+
+#[test]
+fn load_all_songs_in_dir() {
+    let results = load_songs_in_dir(txt_path(""));
+    let txt_count = std::fs::read_dir(txt_path(""))
+        .unwrap()
+        .filter(|x| x.as_ref().unwrap().path().extension() == Some("txt".as_ref()))
+        .count();
+    assert_eq!(results.len(), txt_count);
+    assert!(results
+        .iter()
+        .all(|(path, _)| path.parent() == Some(txt_path("").as_ref())));
+
+    let result = |name: &str| {
+        &results
+            .iter()
+            .find(|(path, _)| path.file_name() == Some(name.as_ref()))
+            .unwrap()
+            .1
+    };
+    assert!(result("komma_in_float.txt").is_ok());
+    assert!(result("garbage_line.txt").is_err());
+}
+
+#[test]
+fn load_all_songs_in_dir_recursive() {
+    let results = load_songs_in_dir_recursive(txt_path(""));
+    let nested_song = results
+        .iter()
+        .find(|(path, _)| path.ends_with("media/nested_song.txt"))
+        .unwrap();
+    assert!(nested_song.1.is_ok());
+    assert_eq!(results.len(), load_songs_in_dir(txt_path("")).len() + 1);
+}
+
+#[test]
+fn load_songs_in_dir_reports_progress() {
+    let mut calls = Vec::new();
+    let results = load_songs_in_dir_with_progress(txt_path(""), |index, total, path| {
+        calls.push((index, total, path.to_path_buf()))
+    });
+    assert_eq!(calls.len(), results.len());
+    for (i, ((index, total, path), (result_path, _))) in calls.iter().zip(&results).enumerate() {
+        assert_eq!(*index, i);
+        assert_eq!(*total, results.len());
+        assert_eq!(path, result_path);
+    }
+}
+
+#[test]
+fn load_songs_in_missing_dir() {
+    let results = load_songs_in_dir(txt_path("does_not_exist"));
+    assert_eq!(results.len(), 1);
+    assert!(results[0].1.is_err());
+}
+
+#[test]
+fn blank_line_in_body() {
+    let path = txt_path("blank_line_in_body.txt");
+    let song = parse_txt_song(&path).unwrap();
+    let (warned_song, warnings) = parse_txt_song_with_warnings(&path).unwrap();
+    assert_eq!(warned_song, song);
+    assert_eq!(song.lines[0].notes.len(), 2);
+    assert_eq!(warnings, vec![Warning::BlankLine { line: 6 }]);
+}
+
+#[test]
+fn song_with_warnings() {
+    let path = txt_path("song_with_warnings.txt");
+    let (song, warnings) = parse_txt_song_with_warnings(&path).unwrap();
+    assert_eq!(
+        warnings,
+        vec![
+            Warning::EmptyTag {
+                line: 5,
+                tag: String::from("GENRE"),
+            },
+            Warning::BlankLine { line: 8 },
+            Warning::CommentLine { line: 9 },
+            Warning::UncanonicalizedPath {
+                tag: "COVER",
+                path: String::from("missing_cover.jpg"),
+            },
+        ]
+    );
+    let audio_path = txt_path("Testfile.mp3").canonicalize().unwrap();
+    assert_eq!(song.header.audio_path, audio_path.display().to_string());
+    assert_eq!(
+        song.header.cover_path,
+        Some(String::from("missing_cover.jpg"))
+    );
+    assert_eq!(song.lines[0].notes.len(), 2);
+
+    // the same problems are errors without warnings
+    assert!(parse_txt_song(&path).is_err());
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_compressed_song() {
+    let song = parse_txt_song(txt_path("komma_in_float.txt.gz")).unwrap();
+    assert_eq!(
+        song,
+        parse_txt_song(txt_path("komma_in_float.txt")).unwrap()
+    );
+}
+
+#[test]
+fn canonicalize_video_file_only() {
+    let song = parse_txt_song(txt_path("video_with_params.txt")).unwrap();
+    let video_path = txt_path("Testfile.mp3").canonicalize().unwrap();
+    assert_eq!(
+        song.header.video_path,
+        Some(format!(
+            "{}, co=cover.jpg,bg = background.jpg",
+            video_path.display()
+        ))
+    );
+    assert_eq!(song.header.video_spec().unwrap().params.len(), 2);
+}
+
+#[test]
+fn fallback_for_unknown_detected_encoding() {
+    // chardet detects IBM855, which has no WHATWG label
+    let song = parse_txt_song(txt_path("undetectable_encoding.txt")).unwrap();
+    assert_eq!(song.lines[0].notes[0].text(), Some("œ„çÒ"));
+}
+
+#[test]
+fn generate_paths_relative_to_base() {
+    let song = parse_txt_song(txt_path("video_with_params.txt")).unwrap();
+    let base = txt_path("").canonicalize().unwrap();
+    let txt = generate_song_txt_relative(&song.header, &song.lines, &base).unwrap();
+    assert_eq!(txt, include_str!("txts/video_with_params.txt").trim_end());
+
+    let txt = generate_song_txt_relative(&song.header, &song.lines, base.join("media")).unwrap();
+    let header = parse_txt_header_str(&txt).unwrap();
+    let parent_path = Path::new("..").join("Testfile.mp3").display().to_string();
+    assert_eq!(header.audio_path, parent_path);
+    assert_eq!(
+        header.video_path,
+        Some(format!("{}, co=cover.jpg,bg = background.jpg", parent_path))
+    );
+}
+
+#[test]
+fn report_undecodable_bytes() {
+    let (song, warnings) = parse_txt_song_with_warnings(txt_path("undecodable_bytes.txt")).unwrap();
+    assert_eq!(song.header.title, "Tëstsöng äöü");
+    assert_eq!(song.lines[0].notes[0].text(), Some("Tëst "));
+    assert_eq!(
+        warnings,
+        vec![Warning::UndecodableBytes {
+            count: 1,
+            offset: 86
+        }]
+    );
+    assert_eq!(
+        parse_txt_song(txt_path("undecodable_bytes.txt")).unwrap(),
+        song
+    );
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn song_from_zip_archive() {
+    let song = parse_txt_song_zip(txt_path("song_pack.zip")).unwrap();
+    let bytes = std::fs::read(txt_path("komma_in_float.txt")).unwrap();
+    assert_eq!(song, parse_txt_song_bytes(&bytes, None).unwrap());
+    assert_eq!(song.header.audio_path, "Testfile.mp3");
+}
+
+#[test]
+fn utf8_fast_path() {
+    for name in &[
+        "bom_utf8.txt",
+        "komma_in_float.txt",
+        "undetectable_encoding.txt",
+    ] {
+        assert_eq!(
+            parse_txt_song_utf8(txt_path(name)).unwrap(),
+            parse_txt_song(txt_path(name)).unwrap()
+        );
+    }
+    let song = parse_txt_song_utf8(txt_path("undecodable_bytes.txt")).unwrap();
+    assert_eq!(song.header.title, "Tëstsöng äöü");
+}
+
+#[test]
+fn detected_encoding_label() {
+    for &(name, expected) in &[
+        ("bom_utf8.txt", "utf-8"),
+        ("undetectable_encoding.txt", "windows-1252"),
+    ] {
+        let (song, label) = parse_txt_song_detect_encoding(txt_path(name)).unwrap();
+        assert_eq!(song, parse_txt_song(txt_path(name)).unwrap());
+        assert_eq!(label, expected);
+    }
+}
+
+fn txt_path(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/txts")
+        .join(name)
+}
@@ -1,3 +1,5 @@
+// the beat literals are cast to Beat, so the tests run with integer and decimal beats
+#![allow(clippy::unnecessary_cast)]
 extern crate ultrastar_txt;
 
 use std::collections::HashMap;
@@ -35,6 +37,28 @@ fn komma_in_float_number() {
     assert!(parse_txt_header_str(txt).is_ok())
 }
 
+#[test]
+fn thousands_separator_in_float_number() {
+    let header_with_gap = |gap: &str| {
+        let txt = song_with_body(&format!("#GAP:{}\n", gap));
+        parse_txt_header_str(&txt)
+    };
+    assert_eq!(header_with_gap("1234,56").unwrap().gap, Some(1234.56));
+    assert_eq!(header_with_gap("1.234,56").unwrap().gap, Some(1234.56));
+    assert_eq!(header_with_gap("1,234.56").unwrap().gap, Some(1234.56));
+    assert_eq!(header_with_gap("1.234.567,5").unwrap().gap, Some(1234567.5));
+    assert_error_kind!(
+        header_with_gap("1,234,56").err().unwrap(),
+        ultrastar_txt::parser::ErrorKind::ValueError(5, "GAP", _, _)
+    );
+}
+
+#[test]
+fn negative_videogap() {
+    let txt = include_str!("txts/negative_videogap.txt");
+    assert_eq!(parse_txt_header_str(txt).unwrap().video_gap, Some(-1.5));
+}
+
 #[test]
 fn missing_essential_header() {
     let txt = include_str!("txts/missing_essential_header.txt");
@@ -49,16 +73,30 @@ fn value_error_in_header_bpm() {
     let txt = include_str!("txts/value_error_in_header_bpm.txt");
     assert_error_kind!(
         parse_txt_header_str(txt).err().unwrap(),
-        ultrastar_txt::parser::ErrorKind::ValueError(5, "BPM")
+        ultrastar_txt::parser::ErrorKind::ValueError(5, "BPM", _, _)
     );
 }
 
+#[test]
+fn non_positive_bpm() {
+    for bpm in &["0", "-120", "inf", "NaN"] {
+        let txt = format!(
+            "#TITLE:Testsong\n#ARTIST:Testartist\n#MP3:Testfile.mp3\n#BPM:{}\n",
+            bpm
+        );
+        assert_error_kind!(
+            parse_txt_header_str(&txt).err().unwrap(),
+            ultrastar_txt::parser::ErrorKind::ValueError(4, "BPM", _, _)
+        );
+    }
+}
+
 #[test]
 fn value_error_in_header_gap() {
     let txt = include_str!("txts/value_error_in_header_gap.txt");
     assert_error_kind!(
         parse_txt_header_str(txt).err().unwrap(),
-        ultrastar_txt::parser::ErrorKind::ValueError(4, "GAP")
+        ultrastar_txt::parser::ErrorKind::ValueError(4, "GAP", _, _)
     );
 }
 
@@ -67,25 +105,58 @@ fn value_error_in_header_videogap() {
     let txt = include_str!("txts/value_error_in_header_videogap.txt");
     assert_error_kind!(
         parse_txt_header_str(txt).err().unwrap(),
-        ultrastar_txt::parser::ErrorKind::ValueError(6, "VIDEOGAP")
+        ultrastar_txt::parser::ErrorKind::ValueError(6, "VIDEOGAP", _, _)
     );
 }
 
+#[test]
+fn non_finite_gap() {
+    for value in &["inf", "-inf", "NaN", "1e40"] {
+        assert_error_kind!(
+            parse_txt_header_str(&song_with_body(&format!("#GAP:{}\n", value)))
+                .err()
+                .unwrap(),
+            ultrastar_txt::parser::ErrorKind::ValueError(5, "GAP", _, _)
+        );
+        assert_error_kind!(
+            parse_txt_header_str(&song_with_body(&format!("#VIDEOGAP:{}\n", value)))
+                .err()
+                .unwrap(),
+            ultrastar_txt::parser::ErrorKind::ValueError(5, "VIDEOGAP", _, _)
+        );
+    }
+}
+
 #[test]
 fn value_error_in_header_year() {
     let txt = include_str!("txts/value_error_in_header_year.txt");
     assert_error_kind!(
         parse_txt_header_str(txt).err().unwrap(),
-        ultrastar_txt::parser::ErrorKind::ValueError(7, "YEAR")
+        ultrastar_txt::parser::ErrorKind::ValueError(7, "YEAR", _, _)
     );
 }
 
+#[test]
+fn implausible_year() {
+    let header_with_year = |year: &str| {
+        let txt = song_with_body(&format!("#YEAR:{}\n", year));
+        parse_txt_header_str(&txt)
+    };
+    assert_eq!(header_with_year("1987").unwrap().year, Some(1987));
+    for year in &["87", "0", "99999"] {
+        assert_error_kind!(
+            header_with_year(year).unwrap_err(),
+            ultrastar_txt::parser::ErrorKind::ValueError(5, "YEAR", _, Some(6))
+        );
+    }
+}
+
 #[test]
 fn unknown_note_type() {
     let txt = include_str!("txts/unknown_note_type.txt");
     assert_error_kind!(
         parse_txt_lines_str(txt).err().unwrap(),
-        ultrastar_txt::parser::ErrorKind::UnknownNoteType(7)
+        ultrastar_txt::parser::ErrorKind::UnknownNoteType(7, _)
     );
 }
 
@@ -94,10 +165,58 @@ fn garbage_line() {
     let txt = include_str!("txts/garbage_line.txt");
     assert_error_kind!(
         parse_txt_lines_str(txt).err().unwrap(),
-        ultrastar_txt::parser::ErrorKind::ParserFailure(7)
+        ultrastar_txt::parser::ErrorKind::ParserFailure(7, _)
+    );
+}
+
+#[test]
+fn line_content_in_errors() {
+    let txt = include_str!("txts/unknown_note_type.txt");
+    let err = parse_txt_lines_str(txt).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "unknown note type in line 7: `{}`",
+            txt.lines().nth(6).unwrap()
+        )
+    );
+
+    let err = parse_txt_header_str("#TITLE:Testsong\n#BPM:fast\n")
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "could not parse BPM in line 2: `#BPM:fast`"
+    );
+
+    // long lines are truncated
+    let txt = format!("#BPM:123\n{}\nE", "a".repeat(100));
+    let err = parse_txt_lines_str(&txt).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        format!("could not parse line 2: `{}...`", "a".repeat(40))
     );
 }
 
+#[test]
+fn column_in_value_errors() {
+    let column = |err: ultrastar_txt::parser::Error| match *err.kind() {
+        ultrastar_txt::parser::ErrorKind::ValueError(_, _, _, column) => column,
+        _ => panic!("unexpected error kind: {:?}", err),
+    };
+    let header = "#TITLE:Testsong\n#ARTIST:Testartist\n#MP3:Testfile.mp3\n";
+    let err = parse_txt_header_str(&format!("{}#BPM:fast\n", header)).unwrap_err();
+    assert_eq!(column(err), Some(5));
+    let err = parse_txt_header_str(&format!("{}#BPM:1\n#GAP:1,2,3\n", header)).unwrap_err();
+    assert_eq!(column(err), Some(5));
+    let err = parse_txt_lines_str("#BPM:123\n: 0  -4 12 a\nE").unwrap_err();
+    assert_eq!(column(err), Some(5));
+    let err = parse_txt_lines_str("#BPM:123\n: 0 4 99999999999 a\nE").unwrap_err();
+    assert_eq!(column(err), Some(6));
+    let err = parse_txt_lines_str("#BPM:123\nP 4\nE").unwrap_err();
+    assert_eq!(column(err), Some(2));
+}
+
 #[test]
 fn duplicate_header_artist() {
     let txt = include_str!("txts/duplicate_header_artist.txt");
@@ -125,6 +244,56 @@ fn duplicate_header_bpm() {
     );
 }
 
+#[test]
+fn duplicate_policy_error() {
+    let txt = include_str!("txts/duplicate_header_bpm_values.txt");
+    let options = ParseOptions {
+        on_duplicate: DuplicatePolicy::Error,
+        ..Default::default()
+    };
+    assert_error_kind!(
+        parse_txt_header_str_with_options(txt, &options)
+            .err()
+            .unwrap(),
+        ultrastar_txt::parser::ErrorKind::DuplicateHeader(6, "BPM")
+    );
+}
+
+#[test]
+fn duplicate_policy_keep_first() {
+    let txt = include_str!("txts/duplicate_header_bpm_values.txt");
+    let options = ParseOptions {
+        on_duplicate: DuplicatePolicy::KeepFirst,
+        ..Default::default()
+    };
+    let header = parse_txt_header_str_with_options(txt, &options).unwrap();
+    assert_eq!(header.bpm, 123.0);
+    assert_eq!(header.gap, Some(666.0));
+}
+
+#[test]
+fn duplicate_policy_keep_last() {
+    let txt = include_str!("txts/duplicate_header_bpm_values.txt");
+    let options = ParseOptions {
+        on_duplicate: DuplicatePolicy::KeepLast,
+        ..Default::default()
+    };
+    let header = parse_txt_header_str_with_options(txt, &options).unwrap();
+    assert_eq!(header.bpm, 246.0);
+    assert_eq!(header.gap, Some(666.0));
+}
+
+#[test]
+fn duplicate_policy_on_unknown_tags() {
+    let txt = include_str!("txts/duplicate_header_unknown.txt");
+    let options = ParseOptions {
+        on_duplicate: DuplicatePolicy::KeepLast,
+        ..Default::default()
+    };
+    let header = parse_txt_header_str_with_options(txt, &options).unwrap();
+    assert_eq!(header.unknown.unwrap()["UNKNOWN"], "Test");
+}
+
 #[test]
 fn duplicate_header_cover() {
     let txt = include_str!("txts/duplicate_header_cover.txt");
@@ -237,10 +406,16 @@ fn missing_end_indicator() {
 fn survive_nonstandard_tags() {
     let txt = include_str!("txts/survive_nonstandard_tags.txt");
     let mut header = get_simple_txt_header();
-    let mut unknown = HashMap::new();
-    unknown.insert(String::from("MedleyStartBeat"), String::from("550"));
-    unknown.insert(String::from("MedleyEndBeat"), String::from("863"));
-    header.unknown = Some(unknown);
+    // the medley tags are recognized, only their spelling is kept
+    header.medley_start_beat = Some(550);
+    header.medley_end_beat = Some(863);
+    let mut spelling = HashMap::new();
+    spelling.insert(
+        String::from("MEDLEYSTARTBEAT"),
+        String::from("MedleyStartBeat"),
+    );
+    spelling.insert(String::from("MEDLEYENDBEAT"), String::from("MedleyEndBeat"));
+    header.tag_spelling = Some(spelling);
     assert_eq!(parse_txt_header_str(txt).unwrap(), header);
 }
 
@@ -256,6 +431,46 @@ fn empty_note_text() {
     assert!(parse_txt_lines_str(txt).is_ok());
 }
 
+#[test]
+fn notes_without_text() {
+    let txt = include_str!("txts/notes_without_text.txt");
+    let lines = parse_txt_lines_str(txt).unwrap();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].notes.len(), 6);
+    for note in lines[0].notes.iter() {
+        assert_eq!(note.pitch(), Some(12));
+        assert_eq!(note.text(), Some(""));
+    }
+    assert_eq!(
+        lines[0].notes[1],
+        Note::Golden {
+            start: 4 as Beat,
+            duration: 4 as Beat,
+            pitch: 12,
+            text: String::new(),
+        }
+    );
+    assert_eq!(
+        lines[0].notes[2],
+        Note::Freestyle {
+            start: 8 as Beat,
+            duration: 4 as Beat,
+            pitch: 12,
+            text: String::new(),
+        }
+    );
+}
+
+#[test]
+fn spaces_in_note_text() {
+    let txt = include_str!("txts/spaces_in_note_text.txt");
+    let header = parse_txt_header_str(txt).unwrap();
+    let lines = parse_txt_lines_str(txt).unwrap();
+    let texts: Vec<_> = lines[0].notes.iter().map(|x| x.text().unwrap()).collect();
+    assert_eq!(texts, vec!["  wide", "two  spaces  ", " "]);
+    assert_eq!(generate_song_txt(&header, &lines).unwrap(), txt);
+}
+
 #[test]
 fn survive_duett_tags() {
     let txt = include_str!("txts/survive_duett_tags.txt");
@@ -268,6 +483,24 @@ fn negative_pitch_in_lines() {
     assert!(parse_txt_lines_str(txt).is_ok());
 }
 
+#[test]
+fn pitch_range() {
+    let txt = song_with_body(": 0 4 -130 low\nE");
+    let lines = parse_txt_lines_str(&txt).unwrap();
+    assert_eq!(lines[0].notes[0].pitch(), Some(-130));
+
+    let options = ParseOptions {
+        pitch_range: Some(MIDI_PITCH_RANGE),
+        ..Default::default()
+    };
+    assert_error_kind!(
+        parse_txt_lines_str_with_options(&txt, &options).unwrap_err(),
+        ultrastar_txt::parser::ErrorKind::ValueError(5, "note pitch", _, Some(6))
+    );
+    let txt = txt.replace("-130", "-36");
+    assert!(parse_txt_lines_str_with_options(&txt, &options).is_ok());
+}
+
 #[test]
 fn negative_start_in_lines() {
     let txt = include_str!("txts/negative_start_in_lines.txt");
@@ -286,6 +519,139 @@ fn missing_space_in_line_break() {
     assert!(parse_txt_lines_str(txt).is_ok());
 }
 
+#[test]
+fn leading_line_break() {
+    let txt = song_with_body("- 100\n: 100 4 59 a\n- 110\n: 110 4 59 b\nE");
+    let lines = parse_txt_lines_str(&txt).unwrap();
+    assert_eq!(
+        lines.iter().map(|x| x.start).collect::<Vec<_>>(),
+        vec![100 as Beat, 110 as Beat]
+    );
+    assert_eq!(
+        lines[0].notes,
+        vec![Note::regular(100 as Beat, 4 as Beat, 59, "a")]
+    );
+    let header = parse_txt_header_str(&txt).unwrap();
+    assert_eq!(generate_song_txt(&header, &lines).unwrap(), txt);
+}
+
+#[test]
+fn bare_line_break() {
+    let txt = song_with_body(": 0 4 59 a\n: 4 6 59 b\n-\n: 12 4 59 c\n- \n: 20 4 59 d\nE");
+    assert_error_kind!(
+        parse_txt_lines_str(&txt).unwrap_err(),
+        ultrastar_txt::parser::ErrorKind::ValueError(7, "line start", _, Some(1))
+    );
+
+    let options = ParseOptions {
+        bare_line_breaks: true,
+        ..Default::default()
+    };
+    let lines = parse_txt_lines_str_with_options(&txt, &options).unwrap();
+    assert_eq!(
+        lines.iter().map(|x| x.start).collect::<Vec<_>>(),
+        vec![0 as Beat, 10 as Beat, 16 as Beat]
+    );
+    assert_eq!(lines[1].notes[0].text(), Some("c"));
+}
+
+#[test]
+fn custom_note_types() {
+    let txt = song_with_body(": 0 4 59 a\nG 4 4 59 b\n* 8 4 59 c\nE");
+    assert_error_kind!(
+        parse_txt_lines_str(&txt).unwrap_err(),
+        ultrastar_txt::parser::ErrorKind::UnknownNoteType(6, _)
+    );
+
+    let mut note_types = HashMap::new();
+    note_types.insert(':', NoteKind::Regular);
+    note_types.insert('G', NoteKind::Golden);
+    note_types.insert('*', NoteKind::Freestyle);
+    let options = ParseOptions {
+        note_types: Some(note_types),
+        ..Default::default()
+    };
+    let lines = parse_txt_lines_str_with_options(&txt, &options).unwrap();
+    assert_eq!(
+        lines[0].notes,
+        vec![
+            Note::regular(0 as Beat, 4 as Beat, 59, "a"),
+            Note::golden(4 as Beat, 4 as Beat, 59, "b"),
+            Note::freestyle(8 as Beat, 4 as Beat, 59, "c"),
+        ]
+    );
+}
+
+#[test]
+fn skip_unknown_note_type() {
+    let txt = include_str!("txts/skip_unknown_note_type.txt");
+    assert_error_kind!(
+        parse_txt_lines_str(txt).unwrap_err(),
+        ultrastar_txt::parser::ErrorKind::UnknownNoteType(6, _)
+    );
+
+    let options = ParseOptions {
+        on_unknown_note: UnknownNotePolicy::Skip,
+        ..Default::default()
+    };
+    let (lines, warnings) = parse_txt_lines_str_with_options_and_warnings(txt, &options).unwrap();
+    assert_eq!(warnings, vec![Warning::UnknownNoteType { line: 6 }]);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(
+        lines[0].notes.iter().map(|x| x.text()).collect::<Vec<_>>(),
+        vec![Some("Test "), Some("'m ")]
+    );
+    assert_eq!(
+        parse_txt_lines_str_with_options(txt, &options).unwrap(),
+        lines
+    );
+}
+
+#[test]
+fn wrapped_header_value() {
+    let txt = include_str!("txts/wrapped_header_value.txt");
+    // the wrapped value ends the header, so the genre is not found and parsing the lyrics fails
+    let header = parse_txt_header_str(txt).unwrap();
+    assert_eq!(header.genre, None);
+    assert_error_kind!(
+        parse_txt_lines_str(txt).unwrap_err(),
+        ultrastar_txt::parser::ErrorKind::ParserFailure(6, _)
+    );
+
+    let options = ParseOptions {
+        header_continuation: true,
+        ..Default::default()
+    };
+    let header = parse_txt_header_str_with_options(txt, &options).unwrap();
+    assert_eq!(header.genre, Some(String::from("Pop")));
+    assert_eq!(
+        header.unknown.unwrap().get("COMMENT"),
+        Some(&String::from(
+            "This is a comment that was wrapped over three lines"
+        ))
+    );
+    let lines = parse_txt_lines_str_with_options(txt, &options).unwrap();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(
+        lines[0].notes[0],
+        Note::regular(0 as Beat, 4 as Beat, 59, "Test ")
+    );
+}
+
+#[test]
+fn note_type_followed_by_digit() {
+    let txt = song_with_body(":0 4 12 x\n*14 2 12 y\nF-2 2 12 z\nE");
+    let lines = parse_txt_lines_str(&txt).unwrap();
+    assert_eq!(
+        lines[0].notes,
+        vec![
+            Note::regular(0 as Beat, 4 as Beat, 12, "x"),
+            Note::golden(14 as Beat, 2 as Beat, 12, "y"),
+            Note::freestyle(-2 as Beat, 2 as Beat, 12, "z"),
+        ]
+    );
+}
+
 #[test]
 fn allow_multiple_spaces_between_line_values() {
     let txt = include_str!("txts/allow_multiple_spaces_between_line_values.txt");
@@ -293,6 +659,49 @@ fn allow_multiple_spaces_between_line_values() {
     assert_eq!(lines, parse_txt_lines_str(txt).unwrap());
 }
 
+#[test]
+fn tab_separated_notes() {
+    let txt = include_str!("txts/tab_separated_notes.txt");
+    let lines = parse_txt_lines_str(txt).unwrap();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1].start, 8 as Beat);
+    assert_eq!(
+        lines[0].notes[0],
+        Note::Regular {
+            start: 0 as Beat,
+            duration: 4 as Beat,
+            pitch: 59,
+            text: String::from("Test"),
+        }
+    );
+
+    // the generator writes spaces instead
+    let header = parse_txt_header_str(txt).unwrap();
+    let generated_txt = generate_song_txt(&header, &lines).unwrap();
+    assert_eq!(generated_txt, txt.replace('\t', " ").trim_end());
+}
+
+#[test]
+fn classify_lines() {
+    assert!(is_note_line(": 0 4 59 Test"));
+    assert!(is_note_line("* 0 4 -1"));
+    assert!(is_note_line("F\t0\t4\t59\tTest\r"));
+    assert!(!is_note_line("M 0 4 59 Test"));
+    assert!(!is_note_line(": 0 4 Test"));
+
+    assert!(is_line_break("- 20"));
+    assert!(is_line_break("-20 "));
+    assert!(!is_line_break("- 20 24"));
+    assert!(is_relative_line_break("- 20 24"));
+    assert!(!is_relative_line_break("- 20"));
+    assert!(!is_line_break("-"));
+
+    assert!(is_player_change("P1"));
+    assert!(is_player_change("P 3"));
+    assert!(!is_player_change("P4"));
+    assert!(!is_player_change(": 0 4 59 P1"));
+}
+
 #[test]
 fn lower_case_relative() {
     let txt = include_str!("txts/lower_case_relative.txt");
@@ -343,11 +752,1714 @@ fn generate_and_reparse_song() {
 fn relative_line_breaks() {
     let txt = include_str!("txts/relative_line_breaks.txt");
     let lines = parse_txt_lines_str(txt).unwrap();
-    assert_eq!(lines[1].rel.unwrap(), 24);
+    assert_eq!(lines[1].rel.unwrap(), 24 as Beat);
 }
 
-fn get_simple_txt_str() -> &'static str {
-    include_str!("txts/simple_txt_with_all_features.txt")
+#[test]
+fn relative_line_break_requires_relative_song() {
+    let txt = include_str!("txts/relative_line_breaks.txt");
+    let mut song = parse_song(txt);
+    assert!(song.is_relative());
+    assert!(generate_song_txt(&song.header, &song.lines).is_ok());
+
+    // contradictory: relative line breaks in an absolute song
+    song.header.relative = Some(false);
+    assert!(!song.is_relative());
+    assert_error_kind!(
+        generate_song_txt(&song.header, &song.lines).unwrap_err(),
+        ultrastar_txt::generator::ErrorKind::RelativeLineBreak(1)
+    );
+    song.header.relative = None;
+    assert!(write_song_txt(&mut String::new(), &song.header, &song.lines).is_err());
+}
+
+#[test]
+fn relative_to_absolute() {
+    let txt = include_str!("txts/relative_song.txt");
+    let song = parse_song(txt);
+    let absolute = to_absolute(&song);
+    assert_eq!(absolute.header.relative, None);
+    assert_eq!(
+        absolute
+            .lines
+            .iter()
+            .map(|x| (x.start, x.rel))
+            .collect::<Vec<_>>(),
+        vec![(0 as Beat, None), (10 as Beat, None), (20 as Beat, None)]
+    );
+    assert_eq!(
+        absolute
+            .lines
+            .iter()
+            .flat_map(|x| x.notes.iter())
+            .map(|x| x.start())
+            .collect::<Vec<_>>(),
+        vec![
+            Some(0 as Beat),
+            Some(4 as Beat),
+            Some(12 as Beat),
+            Some(18 as Beat),
+            None,
+            Some(20 as Beat)
+        ]
+    );
+
+    // absolute songs keep their beats
+    let song = simple_song();
+    let absolute = to_absolute(&song);
+    assert_eq!(absolute.header.relative, None);
+    assert_eq!(absolute.lines, song.lines);
+}
+
+#[test]
+#[cfg(not(feature = "decimal-beats"))]
+fn decimal_beats_rejected_by_default() {
+    let txt = include_str!("txts/decimal_beats.txt");
+    assert_error_kind!(
+        parse_txt_lines_str(txt).err().unwrap(),
+        ultrastar_txt::parser::ErrorKind::ParserFailure(6, _)
+    );
+}
+
+#[test]
+fn display_note() {
+    let regular = Note::Regular {
+        start: 0 as Beat,
+        duration: 4 as Beat,
+        pitch: 12,
+        text: String::from("text"),
+    };
+    let golden = Note::Golden {
+        start: 4 as Beat,
+        duration: 2 as Beat,
+        pitch: -3,
+        text: String::from("gold "),
+    };
+    let freestyle = Note::Freestyle {
+        start: 8 as Beat,
+        duration: 1 as Beat,
+        pitch: 0,
+        text: String::from(""),
+    };
+    let player_change = Note::PlayerChange { player: 2 };
+    assert_eq!(regular.to_string(), ": 0 4 12 text");
+    assert_eq!(golden.to_string(), "* 4 2 -3 gold ");
+    assert_eq!(freestyle.to_string(), "F 8 1 0 ");
+    assert_eq!(player_change.to_string(), "P2");
+}
+
+#[test]
+fn note_constructors() {
+    assert_eq!(
+        Note::regular(0 as Beat, 4 as Beat, 59, "Test"),
+        Note::Regular {
+            start: 0 as Beat,
+            duration: 4 as Beat,
+            pitch: 59,
+            text: String::from("Test"),
+        }
+    );
+    assert_eq!(
+        Note::golden(4 as Beat, 2 as Beat, -1, String::from("I")),
+        Note::Golden {
+            start: 4 as Beat,
+            duration: 2 as Beat,
+            pitch: -1,
+            text: String::from("I"),
+        }
+    );
+    assert_eq!(
+        Note::freestyle(8 as Beat, 1 as Beat, 0, ""),
+        Note::Freestyle {
+            start: 8 as Beat,
+            duration: 1 as Beat,
+            pitch: 0,
+            text: String::new(),
+        }
+    );
+    for player in 1..=3 {
+        assert_eq!(
+            Note::player_change(player),
+            Some(Note::PlayerChange { player })
+        );
+    }
+    assert_eq!(Note::player_change(0), None);
+    assert_eq!(Note::player_change(4), None);
+}
+
+#[test]
+fn display_note_matches_generator() {
+    let header = get_simple_txt_header();
+    let lines = get_simple_txt_lines();
+    let generated_txt = generate_song_txt(&header, &lines).unwrap();
+    for note in lines.iter().flat_map(|line| line.notes.iter()) {
+        assert!(generated_txt.lines().any(|line| line == note.to_string()));
+    }
+}
+
+#[test]
+fn display_header() {
+    let mut header = get_simple_txt_header();
+    let mut unknown = HashMap::new();
+    unknown.insert(String::from("WHAT"), String::from("is this"));
+    unknown.insert(String::from("UNKNOWN"), String::from("tag"));
+    header.unknown = Some(unknown);
+    assert_eq!(
+        header.to_string(),
+        song_with_body(
+            "#GAP:666\n#COVER:Cover.jpg\n#BACKGROUND:BG.jpg\n#VIDEO:DLzxrzFCyOs.mp4\n\
+             #VIDEOGAP:777\n#GENRE:Music\n#EDITION:Testmusic\n#LANGUAGE:en\n#YEAR:1337\n\
+             #RELATIVE:NO\n#UNKNOWN:tag\n#WHAT:is this\n"
+        )
+    );
+}
+
+#[test]
+fn write_song_matches_generate_song() {
+    let header = get_simple_txt_header();
+    let lines = get_simple_txt_lines();
+    let generated_txt = generate_song_txt(&header, &lines).unwrap();
+
+    let mut fmt_txt = String::new();
+    write_song_txt(&mut fmt_txt, &header, &lines).unwrap();
+    assert_eq!(fmt_txt, generated_txt);
+
+    let mut io_txt: Vec<u8> = Vec::new();
+    write_song_txt_io(&mut io_txt, &header, &lines).unwrap();
+    assert_eq!(String::from_utf8(io_txt).unwrap(), generated_txt);
+}
+
+#[test]
+fn collect_all_line_errors() {
+    let txt = include_str!("txts/multiple_errors.txt");
+    let errors = parse_txt_lines_str_collect(txt).err().unwrap();
+    assert_eq!(errors.len(), 3);
+    assert_error_kind!(
+        errors[0],
+        ultrastar_txt::parser::ErrorKind::UnknownNoteType(7, _)
+    );
+    assert_error_kind!(
+        errors[1],
+        ultrastar_txt::parser::ErrorKind::ParserFailure(13, _)
+    );
+    assert_error_kind!(
+        errors[2],
+        ultrastar_txt::parser::ErrorKind::ValueError(15, "player change", _, _)
+    );
+}
+
+#[test]
+fn collect_missing_end_indicator() {
+    let txt = include_str!("txts/missing_end.txt");
+    let errors = parse_txt_lines_str_collect(txt).err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_error_kind!(
+        errors[0],
+        ultrastar_txt::parser::ErrorKind::MissingEndIndicator
+    );
+}
+
+#[test]
+fn collect_without_errors() {
+    let txt = get_simple_txt_str();
+    let lines = get_simple_txt_lines();
+    assert_eq!(lines, parse_txt_lines_str_collect(txt).unwrap());
+}
+
+#[test]
+fn crlf_line_endings() {
+    let txt = include_str!("txts/crlf_line_endings.txt");
+    assert_eq!(parse_txt_header_str(txt).unwrap().bpm, 123.0);
+    let lines = parse_txt_lines_str(txt).unwrap();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].notes[0].text(), Some("Test "));
+    assert_eq!(lines[0].notes[1].text(), Some("I"));
+    assert_eq!(lines[1].notes[0].text(), Some("test"));
+}
+
+#[test]
+fn cr_line_endings() {
+    let txt = include_str!("txts/cr_line_endings.txt");
+    assert_eq!(parse_txt_header_str(txt).unwrap().bpm, 123.0);
+    let lines = parse_txt_lines_str(txt).unwrap();
+    assert_eq!(
+        lines,
+        parse_txt_lines_str(include_str!("txts/crlf_line_endings.txt")).unwrap()
+    );
+    assert_eq!(lines.len(), 2);
+    assert_eq!(parse_txt_songs_str(txt).unwrap().len(), 1);
+}
+
+#[test]
+fn stray_carriage_return() {
+    let txt = "#TITLE:Testsong\r\r\n#ARTIST:Testartist\r\r\n#MP3:Testfile.mp3\r\r\n\
+               #BPM:123\r\r\n: 0 4 59 Test\r\r\n- 4\r\r\n: 4 4 59 I\r\r\nE\r\r\n";
+    assert_eq!(parse_txt_header_str(txt).unwrap().title, "Testsong");
+    let lines = parse_txt_lines_str(txt).unwrap();
+    assert_eq!(lines[0].notes[0].text(), Some("Test"));
+    assert_eq!(lines[1].notes[0].text(), Some("I"));
+}
+
+#[test]
+fn trim_note_text() {
+    let txt = get_simple_txt_str();
+    let options = ParseOptions {
+        trim_note_text: true,
+        ..Default::default()
+    };
+    let lines = parse_txt_lines_str_with_options(txt, &options).unwrap();
+    assert_eq!(lines[0].notes[0].text(), Some("Test"));
+    assert_eq!(lines[0].notes[2].text(), Some("'m"));
+}
+
+#[test]
+fn resolution_and_notes_gap() {
+    let txt = include_str!("txts/resolution_and_notes_gap.txt");
+    let header = parse_txt_header_str(txt).unwrap();
+    assert_eq!(header.resolution, Some(8));
+    assert_eq!(header.notes_gap, Some(-2));
+
+    let lines = parse_txt_lines_str(txt).unwrap();
+    let generated_txt = generate_song_txt(&header, &lines).unwrap();
+    assert!(generated_txt.contains("#RESOLUTION:8\n"));
+    assert!(generated_txt.contains("#NOTESGAP:-2\n"));
+    assert_eq!(
+        parse_txt_header_str(generated_txt.as_ref()).unwrap(),
+        header
+    );
+}
+
+#[test]
+fn encoding_tag() {
+    let txt = include_str!("txts/encoding_tag.txt");
+    let header = parse_txt_header_str(txt).unwrap();
+    assert_eq!(header.encoding, Some(String::from("UTF8")));
+    let lines = parse_txt_lines_str(txt).unwrap();
+    let generated_txt = generate_song_txt(&header, &lines).unwrap();
+    assert!(generated_txt.contains("\n#ENCODING:UTF8\n"));
+    assert_eq!(parse_txt_header_str(&generated_txt).unwrap(), header);
+}
+
+#[test]
+fn medley_tags() {
+    let separate =
+        parse_txt_header_str(&song_with_body("#MEDLEYSTARTBEAT:12\n#MEDLEYENDBEAT:340\n")).unwrap();
+    assert_eq!(separate.medley_start_beat, Some(12));
+    assert_eq!(separate.medley_end_beat, Some(340));
+
+    let combined = parse_txt_header_str(&song_with_body("#MEDLEY:12-340\n")).unwrap();
+    assert_eq!(combined, separate);
+    let generated_txt = generate_song_txt(&combined, &[]).unwrap();
+    assert!(generated_txt.contains("\n#MEDLEYSTARTBEAT:12\n#MEDLEYENDBEAT:340\n"));
+
+    let negative = parse_txt_header_str(&song_with_body("#MEDLEY:-4-20\n")).unwrap();
+    assert_eq!(negative.medley_start_beat, Some(-4));
+    assert_eq!(negative.medley_end_beat, Some(20));
+}
+
+#[test]
+fn value_error_in_header_medley() {
+    let txt = song_with_body("#MEDLEY:12\n");
+    assert_error_kind!(
+        parse_txt_header_str(&txt).err().unwrap(),
+        ultrastar_txt::parser::ErrorKind::ValueError(5, "MEDLEY", _, _)
+    );
+}
+
+#[test]
+fn duplicate_header_medley() {
+    let txt = song_with_body("#MEDLEYSTARTBEAT:12\n#MEDLEY:12-340\n");
+    assert_error_kind!(
+        parse_txt_header_str(&txt).err().unwrap(),
+        ultrastar_txt::parser::ErrorKind::DuplicateHeader(6, "MEDLEY")
+    );
+    let txt = song_with_body("#MEDLEY:12-340\n#MEDLEYENDBEAT:340\n");
+    assert_error_kind!(
+        parse_txt_header_str(&txt).err().unwrap(),
+        ultrastar_txt::parser::ErrorKind::DuplicateHeader(6, "MEDLEYENDBEAT")
+    );
+}
+
+#[test]
+fn bpm_list() {
+    let header = "#TITLE:Testsong\n#ARTIST:Testartist\n#MP3:Testfile.mp3\n";
+    let single = parse_txt_header_str(&format!("{}#BPM:123,5\n", header)).unwrap();
+    assert_eq!(single.bpm, 123.5);
+    assert_eq!(single.bpm_changes, None);
+
+    let list = parse_txt_header_str(&format!("{}#BPM:0 120, 64 140.5\n", header)).unwrap();
+    assert_eq!(list.bpm, 120.0);
+    assert_eq!(
+        list.bpm_changes,
+        Some(vec![(0 as Beat, 120.0), (64 as Beat, 140.5)])
+    );
+    let generated_txt = generate_song_txt(&list, &[]).unwrap();
+    assert!(generated_txt.contains("\n#BPM:0 120, 64 140.5\n"));
+    assert_eq!(parse_txt_header_str(&generated_txt).unwrap(), list);
+
+    // the beats have to be ascending and every entry needs a beat and a bpm
+    for value in &["0 120, 64", "64 120, 0 140", "0 120, 64 -1"] {
+        let txt = format!("{}#BPM:{}\n", header, value);
+        assert_error_kind!(
+            parse_txt_header_str(&txt).err().unwrap(),
+            ultrastar_txt::parser::ErrorKind::ValueError(4, "BPM", _, _)
+        );
+    }
+}
+
+#[test]
+fn generate_relative_keeps_remote_and_relative_paths() {
+    let mut header = get_simple_txt_header();
+    header.audio_path = String::from("https://example.com/song.mp3");
+    let lines = get_simple_txt_lines();
+    let txt = generate_song_txt_relative(&header, &lines, "/songs").unwrap();
+    assert_eq!(txt, generate_song_txt(&header, &lines).unwrap());
+}
+
+#[test]
+#[cfg(unix)]
+fn generate_relative_needs_shared_prefix() {
+    let mut header = get_simple_txt_header();
+    let lines = get_simple_txt_lines();
+    header.audio_path = String::from("/other/Testfile.mp3");
+    let txt = generate_song_txt_relative(&header, &lines, "/songs/a/b").unwrap();
+    assert!(txt.contains("\n#MP3:/other/Testfile.mp3\n"));
+
+    header.audio_path = String::from("/songs/Testfile.mp3");
+    let txt = generate_song_txt_relative(&header, &lines, "/songs/a/b").unwrap();
+    assert!(txt.contains("\n#MP3:../../Testfile.mp3\n"));
+}
+
+#[test]
+fn generate_float_formatting() {
+    let mut header = get_simple_txt_header();
+    header.gap = Some(666.128);
+    let lines = get_simple_txt_lines();
+    let options = GenerateOptions {
+        float_decimals: Some(2),
+        ..Default::default()
+    };
+    let txt = generate_song_txt_with_options(&header, &lines, &options).unwrap();
+    assert!(txt.contains("\n#BPM:123.00\n#GAP:666.13\n"));
+    assert!(txt.contains("\n#VIDEOGAP:777.00\n"));
+
+    let options = GenerateOptions {
+        float_decimals: Some(1),
+        decimal_comma: true,
+        ..Default::default()
+    };
+    let txt = generate_song_txt_with_options(&header, &lines, &options).unwrap();
+    assert!(txt.contains("\n#BPM:123,0\n#GAP:666,1\n"));
+    assert_eq!(parse_txt_header_str(&txt).unwrap().gap, Some(666.1));
+
+    let options = GenerateOptions {
+        decimal_comma: true,
+        ..Default::default()
+    };
+    let txt = generate_song_txt_with_options(&header, &lines, &options).unwrap();
+    assert!(txt.contains("\n#BPM:123\n#GAP:666,128\n"));
+    assert_eq!(parse_txt_header_str(&txt).unwrap(), header);
+}
+
+#[test]
+fn generate_crlf_line_endings() {
+    let header = get_simple_txt_header();
+    let lines = get_simple_txt_lines();
+    let options = GenerateOptions {
+        line_ending: LineEnding::CrLf,
+        ..Default::default()
+    };
+    let txt = generate_song_txt_with_options(&header, &lines, &options).unwrap();
+    assert!(txt.starts_with("#TITLE:Testsong\r\n#ARTIST:Testartist\r\n"));
+    assert!(txt.ends_with("\r\nE"));
+    assert_eq!(txt.matches('\n').count(), txt.matches("\r\n").count());
+    assert_eq!(
+        txt.replace("\r\n", "\n"),
+        generate_song_txt(&header, &lines).unwrap()
+    );
+    assert_eq!(parse_txt_header_str(&txt).unwrap(), header);
+    assert_eq!(parse_txt_lines_str(&txt).unwrap(), lines);
+}
+
+#[test]
+fn reject_line_break_in_note_text() {
+    let header = get_simple_txt_header();
+    let mut lines = get_simple_txt_lines();
+    lines[1].notes[2] = Note::regular(32 as Beat, 4 as Beat, 59, "'m\n: 0 1 2 x");
+    assert_error_kind!(
+        generate_song_txt(&header, &lines).err().unwrap(),
+        ultrastar_txt::generator::ErrorKind::InvalidNoteText(1, 2)
+    );
+    let mut txt = String::new();
+    assert!(write_song_txt(&mut txt, &header, &lines).is_err());
+    assert!(txt.is_empty());
+
+    lines[1].notes[2] = Note::regular(32 as Beat, 4 as Beat, 59, "'m\r");
+    assert!(generate_song_txt(&header, &lines).is_err());
+}
+
+#[test]
+fn reject_line_break_in_header_value() {
+    let mut header = get_simple_txt_header();
+    let lines = get_simple_txt_lines();
+    header.title = String::from("Test\r\nsong");
+    assert_eq!(
+        generate_song_txt(&header, &lines).unwrap_err().to_string(),
+        "line break in the value of tag: TITLE"
+    );
+
+    header = get_simple_txt_header();
+    header.p3 = Some(String::from("Both\n"));
+    let options = GenerateOptions {
+        line_ending: LineEnding::CrLf,
+        ..GenerateOptions::default()
+    };
+    assert_eq!(
+        generate_song_txt_with_options(&header, &lines, &options)
+            .unwrap_err()
+            .to_string(),
+        "line break in the value of tag: P3"
+    );
+
+    header = get_simple_txt_header();
+    let mut unknown = HashMap::new();
+    unknown.insert(String::from("CREATOR"), String::from("me\n#BPM:1"));
+    header.unknown = Some(unknown);
+    assert_eq!(
+        generate_song_txt(&header, &lines).unwrap_err().to_string(),
+        "line break in the value of tag: CREATOR"
+    );
+    let mut txt = String::new();
+    assert!(write_song_txt(&mut txt, &header, &lines).is_err());
+    assert!(txt.is_empty());
+    assert_eq!(
+        generate_header_txt(&header).unwrap_err().to_string(),
+        "line break in the value of tag: CREATOR"
+    );
+    header = get_simple_txt_header();
+    header.title = String::from("Test\nsong");
+    assert_eq!(
+        generate_header_txt(&header).unwrap_err().to_string(),
+        "line break in the value of tag: TITLE"
+    );
+}
+
+#[test]
+fn tag_without_colon() {
+    let txt = "#TITLE:Testsong\n#ARTIST:Testartist\n#SOMETAG\n#MP3:Testfile.mp3\n#BPM:123\n\
+               : 0 4 59 Test\nE";
+    let (header, warnings) = parse_txt_header_str_with_warnings(txt).unwrap();
+    assert_eq!(header.audio_path, "Testfile.mp3");
+    assert_eq!(header.bpm, 123.0);
+    assert_eq!(
+        warnings,
+        vec![Warning::MalformedTag {
+            line: 3,
+            text: String::from("SOMETAG")
+        }]
+    );
+    assert_eq!(parse_txt_header_str(txt).unwrap(), header);
+    assert_eq!(parse_txt_lines_str(txt).unwrap().len(), 1);
+}
+
+#[test]
+fn warn_about_leading_line_break() {
+    let txt = song_with_body("- 10\n: 12 4 59 Test\n- 16\n: 16 4 59 Test\nE");
+    let (lines, warnings) = parse_txt_lines_str_with_warnings(&txt).unwrap();
+    assert_eq!(warnings, vec![Warning::LeadingLineBreak { line: 5 }]);
+    assert_eq!(lines, parse_txt_lines_str(&txt).unwrap());
+    assert_eq!(lines[0].start, 10 as Beat);
+
+    let (_, warnings) = parse_txt_lines_str_with_warnings(get_simple_txt_str()).unwrap();
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn banner_comment_before_header() {
+    let txt = include_str!("txts/banner_comment.txt");
+    let (header, warnings) = parse_txt_header_str_with_warnings(txt).unwrap();
+    assert_eq!(header.title, "Testsong");
+    assert_eq!(header.unknown, None);
+    assert_eq!(
+        warnings,
+        vec![
+            Warning::MalformedTag {
+                line: 1,
+                text: String::from(" generated by SomeEditor 1.2")
+            },
+            Warning::MalformedTag {
+                line: 2,
+                text: String::from(": ------------------------")
+            },
+        ]
+    );
+    assert_eq!(parse_txt_lines_str(txt).unwrap().len(), 1);
+}
+
+#[test]
+fn value_error_in_header_resolution() {
+    let txt = song_with_body("#RESOLUTION:4.5\n");
+    assert_error_kind!(
+        parse_txt_header_str(&txt).err().unwrap(),
+        ultrastar_txt::parser::ErrorKind::ValueError(5, "RESOLUTION", _, _)
+    );
+}
+
+#[test]
+fn song_stats() {
+    let txt = include_str!("txts/survive_duett_tags.txt");
+    let song = parse_song(txt);
+    let stats = SongStats {
+        notes: 10,
+        golden_notes: 1,
+        freestyle_notes: 2,
+        lines: 2,
+        player_changes: 2,
+        lowest_pitch: Some(-1),
+        highest_pitch: Some(59),
+        sung_beats: 38 as Beat,
+    };
+    assert_eq!(song.stats(), stats);
+}
+
+#[test]
+fn golden_notes() {
+    let txt = song_with_body("* 0 4 1 a\n: 4 2 1 b\n- 6\n* 8 3 1 c\nF 11 5 1 d\nE");
+    let song = parse_song(&txt);
+    assert_eq!(
+        song.golden_notes().map(|x| x.text()).collect::<Vec<_>>(),
+        vec![Some("a"), Some("c")]
+    );
+    assert_eq!(song.golden_beat_total(), 7 as Beat);
+
+    let song = TXTSong {
+        header: get_simple_txt_header(),
+        lines: Vec::new(),
+    };
+    assert_eq!(song.golden_notes().count(), 0);
+    assert_eq!(song.golden_beat_total(), 0 as Beat);
+}
+
+#[test]
+fn freestyle_checks() {
+    let mut song = simple_song();
+    assert!(song.contains_freestyle());
+    assert!(!song.is_freestyle_only());
+
+    // only freestyle notes and player changes
+    let txt = song_with_body("P1\nF 0 4 1 a\n- 4\nF 8 3 1 b\nE");
+    song.lines = parse_txt_lines_str(&txt).unwrap();
+    assert!(song.contains_freestyle());
+    assert!(song.is_freestyle_only());
+
+    song.lines = Vec::new();
+    assert!(!song.contains_freestyle());
+    assert!(!song.is_freestyle_only());
+}
+
+#[test]
+fn beat_timing() {
+    let mut header = get_simple_txt_header();
+    header.bpm = 300.0;
+    assert_eq!(header.ms_per_beat(), 50.0);
+    assert_eq!(header.beat_to_ms(10 as Beat), 1166.0);
+    header.gap = None;
+    assert_eq!(header.beat_to_ms(-2 as Beat), -100.0);
+}
+
+#[test]
+fn timed_lines() {
+    let mut header = get_simple_txt_header();
+    header.bpm = 150.0;
+    header.gap = Some(1000.0);
+    let mut lines = get_simple_txt_lines();
+    lines.push(Line {
+        start: 50 as Beat,
+        rel: None,
+        notes: vec![Note::PlayerChange { player: 2 }],
+    });
+    let song = TXTSong { header, lines };
+
+    // a beat takes 100ms at 150 bpm
+    let spans: Vec<(f32, f32)> = song.timed_lines().map(|x| (x.0, x.1)).collect();
+    assert_eq!(
+        spans,
+        vec![(1000.0, 3000.0), (3400.0, 5400.0), (6000.0, 6000.0)]
+    );
+    assert_eq!(song.timed_lines().nth(1).unwrap().2, &song.lines[1]);
+}
+
+#[test]
+fn beat_range() {
+    let mut song = simple_song();
+    assert_eq!(song.beat_range(), Some((0 as Beat, 44 as Beat)));
+
+    // a long note that ends after the last note
+    song.lines[0].notes[1] = Note::Regular {
+        start: 4 as Beat,
+        duration: 60 as Beat,
+        pitch: 0,
+        text: String::new(),
+    };
+    song.lines[1]
+        .notes
+        .insert(0, Note::PlayerChange { player: 1 });
+    assert_eq!(song.beat_range(), Some((0 as Beat, 64 as Beat)));
+
+    song.lines = vec![Line {
+        start: 0 as Beat,
+        rel: None,
+        notes: vec![Note::PlayerChange { player: 1 }],
+    }];
+    assert_eq!(song.beat_range(), None);
+}
+
+#[test]
+fn pitch_statistics() {
+    let mut song = TXTSong {
+        header: get_simple_txt_header(),
+        lines: vec![Line {
+            start: 0 as Beat,
+            rel: None,
+            notes: vec![
+                Note::player_change(1).unwrap(),
+                Note::regular(0 as Beat, 4 as Beat, 55, "a"),
+                Note::golden(4 as Beat, 4 as Beat, 62, "b"),
+                Note::freestyle(8 as Beat, 4 as Beat, 90, "c"),
+                Note::regular(12 as Beat, 4 as Beat, 60, "d"),
+                Note::regular(16 as Beat, 4 as Beat, 58, "e"),
+            ],
+        }],
+    };
+    assert_eq!(
+        song.pitch_stats(),
+        Some(PitchStats {
+            min: 55,
+            max: 62,
+            mean: 58.75,
+            median: 59.0,
+        })
+    );
+
+    song.lines[0].notes.pop();
+    assert_eq!(song.pitch_stats().unwrap().median, 60.0);
+
+    song.lines[0].notes.retain(|note| note.text() == Some("c"));
+    assert_eq!(song.pitch_stats(), None);
+
+    // the median of extreme pitches does not overflow
+    song.lines[0].notes = vec![
+        Note::regular(0 as Beat, 4 as Beat, i32::MAX, "a"),
+        Note::regular(4 as Beat, 4 as Beat, i32::MAX, "b"),
+    ];
+    assert_eq!(song.pitch_stats().unwrap().median, i32::MAX as f32);
+}
+
+#[test]
+fn empty_and_player_change_lines() {
+    let mut line = Line {
+        start: 0 as Beat,
+        rel: None,
+        notes: Vec::new(),
+    };
+    assert!(line.is_empty());
+    assert!(!line.is_player_change_only());
+
+    line.notes.push(Note::player_change(2).unwrap());
+    assert!(!line.is_empty());
+    assert!(line.is_player_change_only());
+
+    line.notes
+        .push(Note::regular(0 as Beat, 4 as Beat, 59, "Test"));
+    assert!(!line.is_empty());
+    assert!(!line.is_player_change_only());
+}
+
+#[test]
+fn first_and_last_beat() {
+    let mut song = simple_song();
+    shift_beats(&mut song.lines, 10 as Beat);
+    assert_eq!(song.first_beat(), 10 as Beat);
+    assert_eq!(song.last_beat(), 54 as Beat);
+
+    song.lines.clear();
+    assert_eq!(song.first_beat(), 0 as Beat);
+    assert_eq!(song.last_beat(), 0 as Beat);
+}
+
+#[test]
+fn shift_gap_of_song() {
+    let mut header = get_simple_txt_header();
+    shift_gap(&mut header, -66.0);
+    assert_eq!(header.gap, Some(600.0));
+}
+
+#[test]
+fn strip_metadata_of_song() {
+    let txt = include_str!("txts/tag_order.txt");
+    let mut header = parse_txt_header_str(txt).unwrap();
+    let lines = parse_txt_lines_str(txt).unwrap();
+    assert!(header.unknown.as_ref().unwrap().contains_key("CREATOR"));
+    strip_metadata(&mut header);
+    assert_eq!(header.unknown, None);
+    assert_eq!(header.title, "Testsong");
+    assert_eq!(header.audio_path, "Testfile.mp3");
+    assert_eq!(header.language, Some(String::from("en")));
+    assert!(!generate_song_txt(&header, &lines)
+        .unwrap()
+        .contains("#CREATOR"));
+}
+
+#[test]
+fn shift_missing_gap() {
+    let mut header = get_simple_txt_header();
+    header.gap = None;
+    shift_gap(&mut header, 42.5);
+    assert_eq!(header.gap, Some(42.5));
+}
+
+#[test]
+fn shift_beats_of_song() {
+    let txt = include_str!("txts/survive_duett_tags.txt");
+    let orig_lines = parse_txt_lines_str(txt).unwrap();
+    let mut lines = orig_lines.clone();
+    shift_beats(&mut lines, 8 as Beat);
+
+    assert_eq!(lines.len(), orig_lines.len());
+    assert_eq!(lines[0].start, 0 as Beat);
+    assert_eq!(lines[1].start, orig_lines[1].start + 8 as Beat);
+    for (line, orig_line) in lines.iter().zip(orig_lines.iter()) {
+        assert_eq!(line.notes.len(), orig_line.notes.len());
+        for (note, orig_note) in line.notes.iter().zip(orig_line.notes.iter()) {
+            assert_eq!(note.start(), orig_note.start().map(|x| x + 8 as Beat));
+            assert_eq!(note.duration(), orig_note.duration());
+            assert_eq!(note.pitch(), orig_note.pitch());
+            assert_eq!(note.text(), orig_note.text());
+            assert_eq!(note.player(), orig_note.player());
+        }
+    }
+}
+
+#[test]
+fn duet_singers() {
+    let txt = include_str!("txts/duet_singers.txt");
+    let header = parse_txt_header_str(txt).unwrap();
+    assert_eq!(header.p1, Some(String::from("Alice")));
+    assert_eq!(header.p2, Some(String::from("Bob")));
+}
+
+#[test]
+fn duet_detection() {
+    // header and player changes
+    let txt = include_str!("txts/duet_singers.txt");
+    let mut song = parse_song(txt);
+    assert!(song.header.is_duet());
+    assert!(song.is_duet());
+
+    // header only
+    song.lines
+        .iter_mut()
+        .for_each(|line| line.notes.retain(|x| x.player().is_none()));
+    assert!(song.is_duet());
+
+    // player changes only
+    let txt = include_str!("txts/survive_duett_tags.txt");
+    let song = parse_song(txt);
+    assert!(!song.header.is_duet());
+    assert!(song.is_duet());
+
+    // neither
+    let song = simple_song();
+    assert!(!song.is_duet());
+}
+
+#[test]
+fn song_players() {
+    let song = simple_song();
+    assert_eq!(song.players(), 1);
+
+    let txt = include_str!("txts/survive_duett_tags.txt");
+    let mut song = parse_song(txt);
+    assert_eq!(song.players(), 2);
+
+    // a part for both players needs no third player
+    song.lines[0].notes.push(Note::player_change(3).unwrap());
+    assert_eq!(song.players(), 2);
+    let mut solo = simple_song();
+    solo.lines[1]
+        .notes
+        .insert(0, Note::player_change(3).unwrap());
+    assert_eq!(solo.players(), 2);
+
+    // header only
+    let txt = include_str!("txts/duet_singers.txt");
+    let mut song = parse_song(txt);
+    song.lines
+        .iter_mut()
+        .for_each(|line| line.notes.retain(|x| x.player().is_none()));
+    assert_eq!(song.players(), 2);
+}
+
+#[test]
+fn song_into_notes() {
+    let lines = get_simple_txt_lines();
+    let song = TXTSong {
+        header: get_simple_txt_header(),
+        lines: lines.clone(),
+    };
+    let notes = song.into_notes();
+    assert_eq!(
+        notes,
+        lines.into_iter().flat_map(|x| x.notes).collect::<Vec<_>>()
+    );
+    assert_eq!(notes.len(), 10);
+}
+
+#[test]
+fn song_notes_at_ms() {
+    let mut song = simple_song();
+    // a beat takes 60000 / (120 * 4) = 125 ms after the gap of 1000 ms
+    song.header.bpm = 120.0;
+    song.header.gap = Some(1000.0);
+    assert!(song.notes_at_ms(0.0).is_empty());
+    assert!(song.notes_at_ms(999.0).is_empty());
+    assert_eq!(song.notes_at_ms(1000.0), vec![&song.lines[0].notes[0]]);
+    assert_eq!(song.notes_at_ms(1499.0), vec![&song.lines[0].notes[0]]);
+    assert_eq!(song.notes_at_ms(1500.0), vec![&song.lines[0].notes[1]]);
+    // the line break from beat 20 to 24
+    assert!(song.notes_at_ms(3600.0).is_empty());
+    assert!(song.notes_at_ms(10000.0).is_empty());
+}
+
+#[test]
+fn song_lines_between() {
+    // the lines span the beats 0 to 20 and 24 to 44
+    let song = simple_song();
+    assert_eq!(song.lines_between(0 as Beat, 100 as Beat).len(), 2);
+    assert_eq!(
+        song.lines_between(10 as Beat, 30 as Beat),
+        vec![&song.lines[0], &song.lines[1]]
+    );
+    assert_eq!(
+        song.lines_between(19 as Beat, 19 as Beat),
+        vec![&song.lines[0]]
+    );
+    assert_eq!(
+        song.lines_between(20 as Beat, 24 as Beat),
+        vec![&song.lines[1]]
+    );
+    assert!(song.lines_between(20 as Beat, 23 as Beat).is_empty());
+    assert!(song.lines_between(44 as Beat, 50 as Beat).is_empty());
+}
+
+#[test]
+fn validate_song() {
+    let mut song = simple_song();
+    assert!(validate(&song).is_empty());
+
+    // overlap within a line
+    song.lines[0].notes[1] = Note::regular(2 as Beat, 4 as Beat, 59, "I");
+    assert_eq!(
+        validate(&song),
+        vec![Issue::OverlappingNotes { line: 0, note: 1 }]
+    );
+
+    // a chorus pasted out of order
+    let mut song = simple_song();
+    let mut chorus = song.lines[0].clone();
+    chorus.start = 10 as Beat;
+    song.lines.push(chorus);
+    assert_eq!(
+        validate(&song),
+        vec![Issue::LineOutOfOrder {
+            line: 2,
+            previous: 1
+        }]
+    );
+    song.header.relative = Some(true);
+    assert!(validate(&song).is_empty());
+}
+
+#[test]
+fn check_suspicious_gap() {
+    let mut song = simple_song();
+    assert_eq!(check_gap(&song), None);
+    song.header.gap = Some(3_600_000.0);
+    assert_eq!(check_gap(&song), Some(Warning::GapTooLarge));
+
+    // a small gap is fine if the notes start right away
+    song.header.gap = Some(12.0);
+    assert_eq!(check_gap(&song), None);
+
+    // the first note starts after 12 seconds
+    shift_beats(&mut song.lines, 100 as Beat);
+    assert_eq!(check_gap(&song), Some(Warning::GapInSeconds));
+    song.header.gap = Some(0.0);
+    assert_eq!(check_gap(&song), None);
+    song.header.gap = Some(12000.0);
+    assert_eq!(check_gap(&song), None);
+}
+
+#[test]
+fn validate_duet_parts_independently() {
+    let txt = include_str!("txts/duet_singers.txt");
+    let song = parse_song(txt);
+    assert!(validate(&song).is_empty());
+}
+
+#[test]
+fn validate_player_numbering() {
+    let mut song = simple_song();
+    song.lines[0]
+        .notes
+        .insert(0, Note::player_change(2).unwrap());
+    assert_eq!(validate(&song), vec![Issue::MissingPlayer { player: 1 }]);
+
+    // a part for both players counts as neither player 1 nor 2
+    song.lines[1]
+        .notes
+        .insert(0, Note::player_change(3).unwrap());
+    assert_eq!(validate(&song), vec![Issue::MissingPlayer { player: 1 }]);
+
+    song.lines[0].notes[0] = Note::player_change(3).unwrap();
+    song.lines[1].notes[0] = Note::player_change(1).unwrap();
+    assert!(validate(&song).is_empty());
+
+    song.lines[1].notes[0] = Note::player_change(2).unwrap();
+    song.lines[1]
+        .notes
+        .insert(0, Note::player_change(1).unwrap());
+    assert!(validate(&song).is_empty());
+
+    // the notes before the first player change belong to player 1
+    let mut song = simple_song();
+    song.lines[1]
+        .notes
+        .insert(0, Note::player_change(2).unwrap());
+    assert!(validate(&song).is_empty());
+}
+
+#[test]
+fn notes_of_one_player() {
+    let txt = song_with_body(
+        "#P1:Alice\n#P2:Bob\n: 0 4 59 a\n- 4\nP2\n: 4 4 59 b\n- 8\n\
+         : 8 4 59 c\nP1\n: 12 4 59 d\n- 16\nP3\n: 16 4 59 e\nE",
+    );
+    let song = parse_song(&txt);
+    let texts = |player| {
+        song.notes_for_player(player)
+            .map(|note| note.text().unwrap())
+            .collect::<Vec<_>>()
+    };
+    // both players sing the part after P3
+    assert_eq!(texts(1), vec!["a", "d", "e"]);
+    assert_eq!(texts(2), vec!["b", "c", "e"]);
+    assert_eq!(texts(3), vec!["e"]);
+    assert!(texts(4).is_empty());
+}
+
+#[test]
+fn both_singers_part_name() {
+    let txt = song_with_body(
+        "#P1:Alice\n#P2:Bob\n#DUETSINGERP3:Carol\nP1\n: 0 4 59 a\nP2\n: 0 4 59 b\nE",
+    );
+    let song = parse_song(&txt);
+    assert_eq!(song.header.p3, Some(String::from("Carol")));
+    assert_eq!(song.players(), 2);
+    let generated_txt = generate_song_txt(&song.header, &song.lines).unwrap();
+    // the spelling of the tag is kept
+    assert!(generated_txt.contains("\n#P1:Alice\n#P2:Bob\n#DUETSINGERP3:Carol\n"));
+    assert_eq!(
+        parse_txt_header_str(&generated_txt).unwrap().p3,
+        song.header.p3
+    );
+}
+
+#[test]
+fn song_content_hash() {
+    let song = simple_song();
+    let mut other = song.clone();
+    other.header.cover_path = Some(String::from("other.jpg"));
+    other.header.gap = None;
+    assert_eq!(song.content_hash(), other.content_hash());
+
+    other.header.bpm = 124.0;
+    assert_ne!(song.content_hash(), other.content_hash());
+    let mut other = song.clone();
+    other.lines[1].notes[0] = Note::golden(24 as Beat, 4 as Beat, 59, "Test ");
+    assert_ne!(song.content_hash(), other.content_hash());
+}
+
+#[test]
+fn export_kar() {
+    let song = simple_song();
+    let kar = kar(&song);
+    let contains = |needle: &[u8]| kar.windows(needle.len()).any(|x| x == needle);
+    assert_eq!(&kar[..4], b"MThd");
+    // format 1 with two tracks
+    assert_eq!(&kar[8..12], &[0, 1, 0, 2]);
+    assert_eq!(kar.windows(4).filter(|x| x == b"MTrk").count(), 2);
+    // 60000000 / 123 microseconds per quarter note
+    assert!(contains(&[0xff, 0x51, 0x03, 0x07, 0x71, 0x7d]));
+    assert!(contains(b"@TTestsong"));
+    assert!(contains(b"/Test "));
+    assert!(contains(b"ing."));
+    assert_eq!(kar.windows(2).filter(|x| x[0] == 0x90).count(), 10);
+}
+
+#[test]
+fn export_kar_note_without_duration() {
+    let mut header = get_simple_txt_header();
+    header.gap = None;
+    let song = TXTSong {
+        header,
+        lines: vec![Line {
+            start: 0 as Beat,
+            rel: None,
+            notes: vec![Note::regular(0 as Beat, 0 as Beat, 60, "x")],
+        }],
+    };
+    let kar = kar(&song);
+    // the note off follows the note on one tick later
+    let events = [0x00, 0x90, 96, 0x64, 0x01, 0x80, 96, 0x00];
+    assert!(kar.windows(events.len()).any(|x| x == events));
+}
+
+#[test]
+fn song_difficulty_estimate() {
+    let header = get_simple_txt_header();
+    let song = |notes: Vec<Note>| TXTSong {
+        header: header.clone(),
+        lines: vec![Line {
+            start: 0 as Beat,
+            rel: None,
+            notes,
+        }],
+    };
+    assert_eq!(song(Vec::new()).difficulty_estimate(), Difficulty::Easy);
+
+    // a beat takes about 122 ms at 123 bpm, so one note every 16 beats is slow
+    let slow = (0..10)
+        .map(|i| Note::regular((i * 16) as Beat, 8 as Beat, 60, "a"))
+        .collect();
+    assert_eq!(song(slow).difficulty_estimate(), Difficulty::Easy);
+
+    // fast notes over an octave
+    let fast = (0..10)
+        .map(|i| Note::regular((i * 2) as Beat, 2 as Beat, 60 + i % 2 * 12, "a"))
+        .collect();
+    assert_eq!(song(fast).difficulty_estimate(), Difficulty::Medium);
+
+    // fast golden notes over two octaves
+    let hard = (0..10)
+        .map(|i| Note::golden((i * 2) as Beat, 2 as Beat, 60 + i % 2 * 24, "a"))
+        .collect();
+    assert_eq!(song(hard).difficulty_estimate(), Difficulty::Hard);
+
+    // the range of extreme pitches does not overflow
+    let extreme = vec![
+        Note::regular(0 as Beat, 8 as Beat, i32::MIN, "a"),
+        Note::regular(16 as Beat, 8 as Beat, i32::MAX, "b"),
+    ];
+    assert_eq!(song(extreme).difficulty_estimate(), Difficulty::Medium);
+}
+
+#[test]
+fn duplicate_duet_singer() {
+    let txt = song_with_body("#P1:Alice\n#DUETSINGERP1:Bob\n");
+    assert_error_kind!(
+        parse_txt_header_str(&txt).err().unwrap(),
+        ultrastar_txt::parser::ErrorKind::DuplicateHeader(6, "P1")
+    );
+}
+
+#[test]
+fn merge_songs_to_duet() {
+    let p1 = simple_song();
+    let mut p2 = p1.clone();
+    p2.header.title = String::from("Second");
+    p2.lines.truncate(1);
+
+    let duet = merge_to_duet(&p1, &p2);
+    assert_eq!(duet.header.p1, Some(String::from("Testsong")));
+    assert_eq!(duet.header.p2, Some(String::from("Second")));
+    assert_eq!(duet.lines.len(), 2);
+    assert_eq!(duet.lines[0].notes[0], Note::PlayerChange { player: 1 });
+    assert_eq!(duet.lines[1].notes[5], Note::PlayerChange { player: 2 });
+    assert_eq!(duet.lines[1].notes[6..], p2.lines[0].notes[..]);
+
+    // the duet survives a round trip
+    let generated_txt = generate_song_txt(&duet.header, &duet.lines).unwrap();
+    assert_eq!(
+        parse_txt_header_str(generated_txt.as_ref()).unwrap(),
+        duet.header
+    );
+    assert_eq!(
+        parse_txt_lines_str(generated_txt.as_ref()).unwrap(),
+        duet.lines
+    );
+}
+
+#[test]
+fn classify_media_paths() {
+    let mut header = get_simple_txt_header();
+    header.cover_path = Some(String::from("https://example.com/Cover.jpg"));
+    header.background_path = Some(String::from("file:///songs/BG.jpg"));
+    header.video_path = None;
+
+    assert_eq!(
+        header.audio_media(),
+        MediaPath::Local(PathBuf::from("Testfile.mp3"))
+    );
+    assert_eq!(
+        header.cover_media(),
+        Some(MediaPath::Remote(String::from(
+            "https://example.com/Cover.jpg"
+        )))
+    );
+    assert_eq!(
+        header.background_media(),
+        Some(MediaPath::Local(PathBuf::from("/songs/BG.jpg")))
+    );
+    assert_eq!(header.video_media(), None);
+    assert!(header.audio_media().is_local());
+    assert!(!header.cover_media().unwrap().is_local());
+}
+
+#[test]
+fn video_parameters() {
+    let mut header = get_simple_txt_header();
+    header.video_path = Some(String::from("clip.mp4, co=cover.jpg,bg = bg.jpg"));
+    let spec = header.video_spec().unwrap();
+    assert_eq!(spec.file, "clip.mp4");
+    let mut params = HashMap::new();
+    params.insert(String::from("co"), String::from("cover.jpg"));
+    params.insert(String::from("bg"), String::from("bg.jpg"));
+    assert_eq!(spec.params, params);
+    assert_eq!(spec.raw_params, ", co=cover.jpg,bg = bg.jpg");
+    assert_eq!(
+        header.video_media(),
+        Some(MediaPath::Local(PathBuf::from("clip.mp4")))
+    );
+
+    // without parameters
+    header.video_path = Some(String::from("clip.mp4"));
+    let spec = header.video_spec().unwrap();
+    assert_eq!(spec.file, "clip.mp4");
+    assert!(spec.params.is_empty());
+    assert!(spec.raw_params.is_empty());
+
+    // a comma that does not start parameters belongs to the file
+    header.video_path = Some(String::from("Artist, Title.mp4"));
+    let spec = header.video_spec().unwrap();
+    assert_eq!(spec.file, "Artist, Title.mp4");
+    assert!(spec.params.is_empty());
+    assert!(spec.raw_params.is_empty());
+
+    header.video_path = None;
+    assert_eq!(header.video_spec(), None);
+}
+
+#[test]
+fn mixed_case_tags() {
+    let txt = include_str!("txts/mixed_case_tags.txt");
+    let header = parse_txt_header_str(txt).unwrap();
+    assert_eq!(header.title, "Testsong");
+    assert_eq!(header.audio_path, "Testfile.mp3");
+    assert_eq!(header.gap, Some(666.0));
+    assert_eq!(header.p1, Some(String::from("Alice")));
+    assert_eq!(header.unknown, None);
+    assert_eq!(header.tag_spelling.as_ref().unwrap().len(), 4);
+}
+
+#[test]
+fn reproduce_tag_spelling() {
+    let txt = include_str!("txts/mixed_case_tags.txt");
+    let header = parse_txt_header_str(txt).unwrap();
+    let lines = parse_txt_lines_str(txt).unwrap();
+    assert_eq!(generate_song_txt(&header, &lines).unwrap(), txt.trim_end());
+}
+
+#[test]
+fn reproduce_tag_order() {
+    let txt = include_str!("txts/tag_order.txt");
+    let options = ParseOptions {
+        keep_tag_order: true,
+        ..Default::default()
+    };
+    let header = parse_txt_header_str_with_options(txt, &options).unwrap();
+    let lines = parse_txt_lines_str(txt).unwrap();
+    assert_eq!(
+        generate_song_txt(&header, &lines).unwrap().as_bytes(),
+        txt.trim_end().as_bytes()
+    );
+
+    // tags without a recorded position are written after the others in the fixed order
+    let mut header = header;
+    header.tag_order.as_mut().unwrap().retain(|x| x != "GAP");
+    header.year = Some(1337);
+    let generated = generate_song_txt(&header, &lines).unwrap();
+    assert!(generated.starts_with("#ARTIST:Testartist\n#TITLE:Testsong\n#CREATOR:Someone\n"));
+    assert!(generated.contains("#MEDLEYSTARTBEAT:4\n#GAP:666\n#YEAR:1337\n: 0 4 59 Test \n"));
+
+    // without the option the fixed order is used
+    let header = parse_txt_header_str(txt).unwrap();
+    assert_eq!(header.tag_order, None);
+    assert!(generate_song_txt(&header, &lines)
+        .unwrap()
+        .starts_with("#TITLE:Testsong\n#ARTIST:Testartist\n"));
+}
+
+#[test]
+fn force_canonical_tag_names() {
+    let txt = include_str!("txts/mixed_case_tags.txt");
+    let header = parse_txt_header_str(txt).unwrap();
+    let lines = parse_txt_lines_str(txt).unwrap();
+    let options = GenerateOptions {
+        canonical_tag_names: true,
+        ..Default::default()
+    };
+    let generated_txt = generate_song_txt_with_options(&header, &lines, &options).unwrap();
+    assert_eq!(
+        generated_txt,
+        song_with_body("#GAP:666\n#P1:Alice\n: 0 4 59 Test\nE")
+    );
+}
+
+#[test]
+fn clamp_overlapping_notes() {
+    let txt = include_str!("txts/overlapping_notes.txt");
+    let mut lines = parse_txt_lines_str(txt).unwrap();
+    clamp_overlaps(&mut lines);
+
+    let starts: Vec<_> = lines[0].notes.iter().map(|x| x.start()).collect();
+    let durations: Vec<_> = lines[0].notes.iter().map(|x| x.duration()).collect();
+    assert_eq!(
+        starts,
+        vec![
+            Some(0 as Beat),
+            Some(4 as Beat),
+            None,
+            Some(6 as Beat),
+            Some(6 as Beat),
+            Some(12 as Beat)
+        ]
+    );
+    // the note before the player change is not limited by the part of the second player
+    assert_eq!(
+        durations,
+        vec![
+            Some(4 as Beat),
+            Some(8 as Beat),
+            None,
+            Some(1 as Beat),
+            Some(6 as Beat),
+            Some(10 as Beat)
+        ]
+    );
+    // the last note of a line is not limited by the next line
+    assert_eq!(lines[1].notes[0].duration(), Some(4 as Beat));
+
+    // both parts of a duet start at the same beat
+    let txt = include_str!("txts/duet_singers.txt");
+    let mut lines = parse_txt_lines_str(txt).unwrap();
+    clamp_overlaps(&mut lines);
+    assert_eq!(lines, parse_txt_lines_str(txt).unwrap());
+
+    // notes that are out of order are not cut
+    let mut lines = vec![Line {
+        start: 0 as Beat,
+        rel: None,
+        notes: vec![
+            Note::regular(8 as Beat, 4 as Beat, 59, "b"),
+            Note::regular(0 as Beat, 4 as Beat, 59, "a"),
+        ],
+    }];
+    let unsorted = lines.clone();
+    clamp_overlaps(&mut lines);
+    assert_eq!(lines, unsorted);
+}
+
+#[test]
+fn sort_shuffled_lines() {
+    let txt = song_with_body(
+        ": 4 2 1 b\n: 0 2 1 a\n- 30\n: 34 2 1 f\n: 30 2 1 e\n\
+         - 10\n: 16 2 1 d\nP2\n: 12 2 1 c\n: 10 2 1 x\nE",
+    );
+    let mut song = parse_song(&txt);
+    sort_lines(&mut song);
+    assert_eq!(
+        song.lines.iter().map(|x| x.start).collect::<Vec<_>>(),
+        vec![0 as Beat, 10 as Beat, 30 as Beat]
+    );
+    let texts: Vec<Vec<&str>> = song
+        .lines
+        .iter()
+        .map(|x| x.notes.iter().map(|x| x.text().unwrap_or("P")).collect())
+        .collect();
+    assert_eq!(
+        texts,
+        vec![vec!["a", "b"], vec!["d", "P", "x", "c"], vec!["e", "f"]]
+    );
+}
+
+#[test]
+fn quantize_notes() {
+    let txt = song_with_body(": 1 3 1 a\n: 5 1 1 b\nP2\n- 9\n* 10 5 1 c\nF -3 2 1 d\nE");
+    let mut lines = parse_txt_lines_str(&txt).unwrap();
+    quantize(&mut lines, 4 as Beat);
+    let notes: Vec<_> = lines
+        .iter()
+        .flat_map(|x| x.notes.iter())
+        .map(|x| (x.start(), x.duration()))
+        .collect();
+    assert_eq!(
+        notes,
+        vec![
+            (Some(0 as Beat), Some(4 as Beat)),
+            (Some(4 as Beat), Some(1 as Beat)),
+            (None, None),
+            (Some(12 as Beat), Some(4 as Beat)),
+            (Some(-4 as Beat), Some(4 as Beat)),
+        ]
+    );
+    // line breaks are not changed
+    assert_eq!(lines[1].start, 9 as Beat);
+}
+
+#[test]
+fn keep_body_comments() {
+    let txt = include_str!("txts/body_comments.txt");
+    let (lines, comments) = parse_txt_lines_str_with_comments(txt).unwrap();
+    assert_eq!(lines, parse_txt_lines_str(txt).unwrap());
+    assert_eq!(
+        comments,
+        vec![
+            (6, String::from(" verse one")),
+            (9, String::from("chorus")),
+            (11, String::from(" outro")),
+        ]
+    );
+}
+
+#[test]
+fn regenerate_body_comments() {
+    let txt = include_str!("txts/body_comments.txt");
+    let header = parse_txt_header_str(txt).unwrap();
+    let (lines, comments) = parse_txt_lines_str_with_comments(txt).unwrap();
+    let generated_txt = generate_song_txt_with_comments(&header, &lines, &comments).unwrap();
+    assert_eq!(generated_txt, txt.trim_end());
+}
+
+#[test]
+fn reject_line_break_in_comment() {
+    let header = get_simple_txt_header();
+    let lines = get_simple_txt_lines();
+    let comments = vec![(5, String::from("a comment\n: 0 4 59 x"))];
+    assert_error_kind!(
+        generate_song_txt_with_comments(&header, &lines, &comments).unwrap_err(),
+        ultrastar_txt::generator::ErrorKind::InvalidComment(5)
+    );
+}
+
+#[test]
+fn generate_header_stub() {
+    let header = get_simple_txt_header();
+    let stub = generate_header_txt(&header).unwrap();
+    assert!(stub.starts_with("#TITLE:Testsong\n#ARTIST:Testartist\n"));
+    assert!(stub.ends_with("\nE"));
+    assert_eq!(parse_txt_header_str(&stub).unwrap(), header);
+    assert_eq!(
+        parse_txt_lines_str(&stub).unwrap(),
+        vec![Line {
+            start: 0 as Beat,
+            rel: None,
+            notes: Vec::new(),
+        }]
+    );
+}
+
+#[test]
+fn merge_adjacent_notes() {
+    let mut lines = vec![Line {
+        start: 0 as Beat,
+        rel: None,
+        notes: vec![
+            Note::regular(0 as Beat, 2 as Beat, 59, "Hel"),
+            Note::regular(2 as Beat, 2 as Beat, 59, "lo "),
+            Note::regular(4 as Beat, 2 as Beat, 59, "wor"),
+            Note::regular(6 as Beat, 2 as Beat, 60, "ld "),
+            Note::golden(9 as Beat, 2 as Beat, 60, "and "),
+            Note::golden(11 as Beat, 4 as Beat, 60, "more"),
+            Note::freestyle(15 as Beat, 2 as Beat, 60, "!"),
+            Note::player_change(2).unwrap(),
+            Note::freestyle(17 as Beat, 2 as Beat, 60, "?"),
+        ],
+    }];
+    merge_adjacent(&mut lines);
+    assert_eq!(
+        lines[0].notes,
+        vec![
+            Note::regular(0 as Beat, 6 as Beat, 59, "Hello wor"),
+            Note::regular(6 as Beat, 2 as Beat, 60, "ld "),
+            Note::golden(9 as Beat, 6 as Beat, 60, "and more"),
+            Note::freestyle(15 as Beat, 2 as Beat, 60, "!"),
+            Note::player_change(2).unwrap(),
+            Note::freestyle(17 as Beat, 2 as Beat, 60, "?"),
+        ]
+    );
+}
+
+#[test]
+fn normalize_pitches_to_octave() {
+    let txt = song_with_body(
+        ": 0 1 -13 a\n: 1 1 -1 b\n* 2 1 0 c\nP2\nF 3 1 11 d\n: 4 1 12 e\n: 5 1 59 f\nE",
+    );
+    let mut lines = parse_txt_lines_str(&txt).unwrap();
+    normalize_pitch_octave(&mut lines);
+    assert_eq!(
+        lines[0].notes.iter().map(|x| x.pitch()).collect::<Vec<_>>(),
+        vec![
+            Some(11),
+            Some(11),
+            Some(0),
+            None,
+            Some(11),
+            Some(0),
+            Some(11)
+        ]
+    );
+    assert_eq!(lines[0].notes[3], Note::PlayerChange { player: 2 });
+}
+
+#[test]
+fn visit_notes_and_lines() {
+    let mut lines = get_simple_txt_lines();
+    visit_notes_mut(&mut lines, |note| match *note {
+        Note::Regular { ref mut text, .. }
+        | Note::Golden { ref mut text, .. }
+        | Note::Freestyle { ref mut text, .. } => *text = text.to_uppercase(),
+        Note::PlayerChange { .. } => (),
+    });
+    assert_eq!(
+        lines[0].notes.iter().map(|x| x.text()).collect::<Vec<_>>(),
+        vec![
+            Some("TEST "),
+            Some("I"),
+            Some("'M "),
+            Some("TEST"),
+            Some("ING.")
+        ]
+    );
+
+    let mut count = 0;
+    visit_lines_mut(&mut lines, |line| {
+        line.start += 1 as Beat;
+        count += 1;
+    });
+    assert_eq!(count, 2);
+    assert_eq!(lines[1].start, 21 as Beat);
+}
+
+#[test]
+fn remove_golden_and_freestyle_notes() {
+    let txt = song_with_body(": 0 1 1 a\n* 1 2 2 b\nP2\nF 3 4 3 c\nE");
+    let mut lines = parse_txt_lines_str(&txt).unwrap();
+    remove_golden(&mut lines);
+    assert_eq!(
+        lines[0].notes,
+        vec![
+            Note::regular(0 as Beat, 1 as Beat, 1, "a"),
+            Note::regular(1 as Beat, 2 as Beat, 2, "b"),
+            Note::PlayerChange { player: 2 },
+            Note::freestyle(3 as Beat, 4 as Beat, 3, "c"),
+        ]
+    );
+    remove_freestyle(&mut lines);
+    assert_eq!(
+        lines[0].notes[3],
+        Note::regular(3 as Beat, 4 as Beat, 3, "c")
+    );
+}
+
+#[test]
+fn line_at_beat() {
+    let song = simple_song();
+    assert_eq!(song.line_at_beat(-1 as Beat), None);
+    assert_eq!(song.line_at_beat(0 as Beat), Some(&song.lines[0]));
+    assert_eq!(song.line_at_beat(19 as Beat), Some(&song.lines[0]));
+    assert_eq!(song.line_at_beat(20 as Beat), Some(&song.lines[1]));
+    assert_eq!(song.line_at_beat(43 as Beat), Some(&song.lines[1]));
+    assert_eq!(song.line_at_beat(44 as Beat), None);
+}
+
+#[test]
+fn generate_relative_tag() {
+    let mut header = get_simple_txt_header();
+    let lines = get_simple_txt_lines();
+    let options = GenerateOptions {
+        omit_relative_no: true,
+        ..Default::default()
+    };
+
+    header.relative = None;
+    let generated_txt = generate_song_txt(&header, &lines).unwrap();
+    assert!(!generated_txt.contains("#RELATIVE"));
+
+    header.relative = Some(false);
+    let generated_txt = generate_song_txt(&header, &lines).unwrap();
+    assert!(generated_txt.contains("#RELATIVE:NO\n"));
+    let generated_txt = generate_song_txt_with_options(&header, &lines, &options).unwrap();
+    assert!(!generated_txt.contains("#RELATIVE"));
+
+    header.relative = Some(true);
+    let generated_txt = generate_song_txt_with_options(&header, &lines, &options).unwrap();
+    assert!(generated_txt.contains("#RELATIVE:YES\n"));
+}
+
+#[test]
+#[cfg(feature = "file-support")]
+fn path_encoding_check() {
+    let mut header = get_simple_txt_header();
+    let lines = get_simple_txt_lines();
+    let options = GenerateOptions {
+        path_encoding: Some(String::from("windows-1252")),
+        ..Default::default()
+    };
+    header.audio_path = String::from("Überlied.mp3");
+    assert!(generate_song_txt_with_options(&header, &lines, &options).is_ok());
+
+    header.cover_path = Some(String::from("日本.jpg"));
+    assert_error_kind!(
+        generate_song_txt_with_options(&header, &lines, &options).unwrap_err(),
+        ultrastar_txt::generator::ErrorKind::InvalidPathEncoding("COVER")
+    );
+    assert!(generate_song_txt(&header, &lines).is_ok());
+
+    let options = GenerateOptions {
+        path_encoding: Some(String::from("no-such-encoding")),
+        ..Default::default()
+    };
+    assert_error_kind!(
+        generate_song_txt_with_options(&header, &lines, &options).unwrap_err(),
+        ultrastar_txt::generator::ErrorKind::UnknownEncoding(_)
+    );
+}
+
+#[test]
+fn common_traits() {
+    fn assert_traits<T: Clone + PartialEq + std::fmt::Debug>(x: T) {
+        assert_eq!(x.clone(), x);
+    }
+    #[cfg(not(feature = "decimal-beats"))]
+    fn assert_eq_trait<T: Eq>(_: &T) {}
+
+    let song = simple_song();
+    #[cfg(not(feature = "decimal-beats"))]
+    {
+        assert_eq_trait(&song.lines[0]);
+        assert_eq_trait(&song.lines[0].notes[0]);
+        assert_eq_trait(&song.stats());
+    }
+    assert_traits(song.stats());
+    assert_traits(song.header.clone());
+    assert_traits(song.lines[0].notes[0].clone());
+    assert_traits(song.lines[0].clone());
+    assert_traits(song);
+}
+
+#[test]
+fn diff_two_songs() {
+    let a = simple_song();
+    assert!(diff_songs(&a, &a.clone()).is_empty());
+
+    let mut b = a.clone();
+    b.header.title = String::from("Changed");
+    b.header.gap = None;
+    let mut unknown = HashMap::new();
+    unknown.insert(String::from("CREATOR"), String::from("Someone"));
+    b.header.unknown = Some(unknown);
+    b.lines[1].start = 22 as Beat;
+    b.lines[1].notes[2] = Note::PlayerChange { player: 2 };
+    b.lines[1].notes.pop();
+    b.lines.push(Line {
+        start: 50 as Beat,
+        rel: None,
+        notes: Vec::new(),
+    });
+
+    let diff = diff_songs(&a, &b);
+    assert_eq!(diff.header, vec!["TITLE", "GAP", "CREATOR"]);
+    assert_eq!(
+        diff.lines,
+        vec![
+            LineDiff::Modified {
+                index: 1,
+                start_changed: true,
+                notes: vec![NoteDiff::Modified(2), NoteDiff::Removed(4)],
+            },
+            LineDiff::Added(2),
+        ]
+    );
+    assert_eq!(diff_songs(&b, &a).lines[1], LineDiff::Removed(2));
+}
+
+#[test]
+fn concatenated_songs() {
+    let txt = include_str!("txts/concatenated_songs.txt");
+    let songs = parse_txt_songs_str(txt).unwrap();
+    assert_eq!(songs.len(), 2);
+    assert_eq!(songs[0].header.title, "First");
+    assert_eq!(songs[0].lines.len(), 1);
+    assert_eq!(songs[1].header.title, "Second");
+    assert_eq!(songs[1].header.bpm, 246.0);
+    assert_eq!(songs[1].lines.len(), 2);
+
+    // trailing blank lines do not start another song
+    assert_eq!(
+        parse_txt_songs_str(&format!("{}\n\n", txt)).unwrap().len(),
+        2
+    );
+
+    // line numbers refer to the whole string
+    let broken = txt.replace("Three", "Three\nM 12 4 59 Four");
+    assert_error_kind!(
+        parse_txt_songs_str(&broken).unwrap_err(),
+        ultrastar_txt::parser::ErrorKind::UnknownNoteType(15, _)
+    );
+    let unterminated = txt.trim_end_matches('E');
+    assert_error_kind!(
+        parse_txt_songs_str(unterminated).unwrap_err(),
+        ultrastar_txt::parser::ErrorKind::MissingEndIndicator
+    );
+}
+
+fn get_simple_txt_str() -> &'static str {
+    include_str!("txts/simple_txt_with_all_features.txt")
+}
+
+// returns the txt of a song with the mandatory tags of the simple song followed by body
+fn song_with_body(body: &str) -> String {
+    format!(
+        "#TITLE:Testsong\n#ARTIST:Testartist\n#MP3:Testfile.mp3\n#BPM:123\n{}",
+        body
+    )
+}
+
+// parses the header and the lines of a song that has to be valid
+fn parse_song(txt: &str) -> TXTSong {
+    TXTSong {
+        header: parse_txt_header_str(txt).unwrap(),
+        lines: parse_txt_lines_str(txt).unwrap(),
+    }
+}
+
+// returns the header and the lines of the simple txt as a song
+fn simple_song() -> TXTSong {
+    TXTSong {
+        header: get_simple_txt_header(),
+        lines: get_simple_txt_lines(),
+    }
 }
 
 fn get_simple_txt_header() -> Header {
@@ -355,90 +2467,101 @@ fn get_simple_txt_header() -> Header {
         artist: String::from("Testartist"),
         title: String::from("Testsong"),
         bpm: 123.0,
-        audio_path: PathBuf::from("Testfile.mp3"),
+        bpm_changes: None,
+        audio_path: String::from("Testfile.mp3"),
         gap: Some(666.0),
         relative: Some(false),
-        video_path: Some(PathBuf::from("DLzxrzFCyOs.mp4")),
-        cover_path: Some(PathBuf::from("Cover.jpg")),
-        background_path: Some(PathBuf::from("BG.jpg")),
+        video_path: Some(String::from("DLzxrzFCyOs.mp4")),
+        cover_path: Some(String::from("Cover.jpg")),
+        background_path: Some(String::from("BG.jpg")),
         video_gap: Some(777.0),
         genre: Some(String::from("Music")),
         edition: Some(String::from("Testmusic")),
         language: Some(String::from("en")),
         year: Some(1337),
+        resolution: None,
+        notes_gap: None,
+        encoding: None,
+        medley_start_beat: None,
+        medley_end_beat: None,
+        p1: None,
+        p2: None,
+        p3: None,
         unknown: None,
+        tag_spelling: None,
+        tag_order: None,
     }
 }
 
 fn get_simple_txt_lines() -> Vec<Line> {
     vec![
         Line {
-            start: 0,
+            start: 0 as Beat,
             rel: None,
             notes: vec![
                 Note::Regular {
-                    start: 0,
-                    duration: 4,
+                    start: 0 as Beat,
+                    duration: 4 as Beat,
                     pitch: 59,
                     text: String::from("Test "),
                 },
                 Note::Regular {
-                    start: 4,
-                    duration: 4,
+                    start: 4 as Beat,
+                    duration: 4 as Beat,
                     pitch: 59,
                     text: String::from("I"),
                 },
                 Note::Regular {
-                    start: 8,
-                    duration: 4,
+                    start: 8 as Beat,
+                    duration: 4 as Beat,
                     pitch: 59,
                     text: String::from("'m "),
                 },
                 Note::Golden {
-                    start: 12,
-                    duration: 4,
+                    start: 12 as Beat,
+                    duration: 4 as Beat,
                     pitch: 59,
                     text: String::from("test"),
                 },
                 Note::Regular {
-                    start: 16,
-                    duration: 4,
+                    start: 16 as Beat,
+                    duration: 4 as Beat,
                     pitch: 59,
                     text: String::from("ing."),
                 },
             ],
         },
         Line {
-            start: 20,
+            start: 20 as Beat,
             rel: None,
             notes: vec![
                 Note::Regular {
-                    start: 24,
-                    duration: 4,
+                    start: 24 as Beat,
+                    duration: 4 as Beat,
                     pitch: 59,
                     text: String::from("Test "),
                 },
                 Note::Regular {
-                    start: 28,
-                    duration: 4,
+                    start: 28 as Beat,
+                    duration: 4 as Beat,
                     pitch: 59,
                     text: String::from("I"),
                 },
                 Note::Regular {
-                    start: 32,
-                    duration: 4,
+                    start: 32 as Beat,
+                    duration: 4 as Beat,
                     pitch: 59,
                     text: String::from("'m "),
                 },
                 Note::Freestyle {
-                    start: 36,
-                    duration: 4,
+                    start: 36 as Beat,
+                    duration: 4 as Beat,
                     pitch: 59,
                     text: String::from("test"),
                 },
                 Note::Freestyle {
-                    start: 40,
-                    duration: 4,
+                    start: 40 as Beat,
+                    duration: 4 as Beat,
                     pitch: 59,
                     text: String::from("ing."),
                 },